@@ -0,0 +1,509 @@
+// Fingerprinting of the static assets: every file of the static folder (and the generated
+// stylesheet) is given a copy named after the hash of its content (e.g.
+// styles/tailwind.3f9a1c.css), so browsers never keep a stale copy once the site is deployed.
+// The logical paths are mapped to the fingerprinted ones in the asset manifest
+// (asset-manifest.json in the build folder), which templates use through `asset_url`
+//
+// Stylesheets and scripts are also given a Subresource Integrity digest (SHA-384), exposed to
+// the templates through `asset_integrity` for the `integrity` attribute of <link>/<script> tags
+use crate::cache;
+use crate::error::BuildError;
+use crate::resources::{StaticAction, StaticRules};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use log::{info, warn};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha384};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use walkdir::WalkDir;
+
+// Version of the asset manifest file format
+const ASSET_MANIFEST_VERSION: u32 = 1;
+
+// Number of hex characters of the content hash within the fingerprinted file names
+const HASH_LENGTH: usize = 6;
+
+// Extensions of the assets given an integrity digest (stylesheets and scripts)
+const INTEGRITY_EXTENSIONS: [&str; 3] = ["css", "js", "mjs"];
+
+// Asset settings from the configuration YAML (the `assets` section)
+// - fingerprint: give assets content-hashed file names. When disabled, `asset_url` resolves to
+//   the plain file names
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AssetConfig {
+    pub fingerprint: bool,
+}
+
+impl Default for AssetConfig {
+    fn default() -> Self {
+        AssetConfig { fingerprint: true }
+    }
+}
+
+// The manifest file maps the logical paths to the fingerprinted ones (`assets`), and to the
+// integrity digests of the stylesheets and scripts (`integrity`)
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ManifestFile {
+    version: u32,
+    assets: BTreeMap<String, String>,
+    #[serde(default)]
+    integrity: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Asset {
+    path: String,
+    integrity: Option<String>,
+}
+
+type Assets = Arc<RwLock<BTreeMap<String, Asset>>>;
+
+// Logical path (relative to the static folder of the build, e.g. "styles/tailwind.css") ->
+// fingerprinted path (e.g. "styles/tailwind.3f9a1c.css"). The manifest of the previous build is
+// kept to find out whether the pages reference outdated assets, and to remove the stale copies
+pub struct AssetManifest {
+    path: PathBuf,
+    static_output_dir: PathBuf,
+    fingerprint: bool,
+    previous: BTreeMap<String, Asset>,
+    assets: Assets,
+}
+
+impl AssetManifest {
+    pub fn load_or_default(output_dir: &Path, config: &AssetConfig) -> Self {
+        let path = output_dir.join("asset-manifest.json");
+        let previous = match fs::read_to_string(&path) {
+            Ok(data) => match serde_json::from_str::<ManifestFile>(&data) {
+                Ok(manifest) if manifest.version == ASSET_MANIFEST_VERSION => manifest.into(),
+                _ => {
+                    warn!("Discarding unreadable asset manifest {:?}", path);
+                    BTreeMap::new()
+                }
+            },
+            Err(_) => BTreeMap::new(),
+        };
+        AssetManifest {
+            path,
+            static_output_dir: output_dir.join("static"),
+            fingerprint: config.fingerprint,
+            previous,
+            assets: Arc::new(RwLock::new(BTreeMap::new())),
+        }
+    }
+
+    // Add every file of the static folder (under the name it is given in the build folder, e.g.
+    // with the WebP extension for optimized images). Ignored files are left out
+    pub fn add_static_folder(&self, static_dir: &Path, rules: &StaticRules) -> Vec<BuildError> {
+        let files: Vec<PathBuf> = WalkDir::new(static_dir)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_file())
+            .filter_map(|entry| {
+                let relative = entry.path().strip_prefix(static_dir).ok()?;
+                (rules.action_for(relative) != StaticAction::Ignore)
+                    .then(|| rules.output_path_for(relative))
+            })
+            .collect();
+        files
+            .par_iter()
+            .filter_map(|path| self.add(path).err())
+            .collect()
+    }
+
+    // Add the file at the given path (relative to the static folder of the build), and create
+    // its fingerprinted copy. Returns whether the entry changed. A file that is yet to be
+    // generated (e.g. the stylesheet on the first build) resolves to its plain name, without an
+    // integrity digest
+    pub fn add(&self, path: &Path) -> Result<bool, BuildError> {
+        let key = asset_key(path);
+        let source = self.static_output_dir.join(path);
+        let io_error = |e| BuildError::io(&source, e);
+        let asset = if source.is_file() {
+            let fingerprinted = if self.fingerprint {
+                let hash = cache::compute_file_hash(&source).map_err(io_error)?;
+                let fingerprinted = fingerprinted_path(path, &hash[..HASH_LENGTH]);
+                let destination = self.static_output_dir.join(&fingerprinted);
+                if !destination.exists() {
+                    info!("Fingerprinting {:?} as {:?}", source, destination);
+                    fs::copy(&source, &destination).map_err(|e| BuildError::io(&destination, e))?;
+                }
+                asset_key(&fingerprinted)
+            } else {
+                key.clone()
+            };
+            let integrity = if has_integrity(&key) {
+                Some(integrity_digest(&source).map_err(io_error)?)
+            } else {
+                None
+            };
+            Asset {
+                path: fingerprinted,
+                integrity,
+            }
+        } else {
+            Asset {
+                path: key.clone(),
+                integrity: None,
+            }
+        };
+
+        let previous = self.assets.write().unwrap().insert(key, asset.clone());
+        Ok(previous.as_ref() != Some(&asset))
+    }
+
+    // Whether any asset was added, removed or changed since the previous build (in which case
+    // the pages rendered by the previous build reference outdated URLs)
+    pub fn changed(&self) -> bool {
+        *self.assets.read().unwrap() != self.previous
+    }
+
+    // Fingerprinted path (relative to the static folder of the build) of a file referenced by
+    // its source path, e.g. an image within the content. Falls back to the plain output path for
+    // files missing from the static folder
+    pub fn resolve(&self, rules: &StaticRules, path: &Path) -> String {
+        let output_path = asset_key(&rules.output_path_for(path));
+        let assets = self.assets.read().unwrap();
+        match assets.get(&output_path) {
+            Some(asset) => asset.path.clone(),
+            None => output_path,
+        }
+    }
+
+    // Tera functions resolving the URL and the integrity digest of an asset (sharing the
+    // entries of the manifest)
+    pub fn asset_url(&self) -> AssetUrl {
+        AssetUrl {
+            assets: Arc::clone(&self.assets),
+        }
+    }
+
+    pub fn asset_integrity(&self) -> AssetIntegrity {
+        AssetIntegrity {
+            assets: Arc::clone(&self.assets),
+        }
+    }
+
+    // Remove the fingerprinted copies of the previous build that are no longer part of the
+    // manifest (i.e. the content of the asset changed, or the asset was removed)
+    pub fn prune(&self, dry_run: bool) -> Result<Vec<PathBuf>, BuildError> {
+        let assets = self.assets.read().unwrap();
+        let current: BTreeSet<&String> = assets.values().map(|asset| &asset.path).collect();
+        let mut stale_files = Vec::new();
+        for (key, asset) in &self.previous {
+            let path = self.static_output_dir.join(&asset.path);
+            if &asset.path != key && !current.contains(&asset.path) && path.exists() {
+                cache::remove_stale_file(&path, dry_run)?;
+                stale_files.push(path);
+            }
+        }
+        Ok(stale_files)
+    }
+
+    // Write the manifest to the disk if it changed since the previous build
+    pub fn save(&self) -> Result<(), BuildError> {
+        if !self.changed() && self.path.exists() {
+            return Ok(());
+        }
+        let assets = self.assets.read().unwrap();
+        let manifest = ManifestFile {
+            version: ASSET_MANIFEST_VERSION,
+            assets: assets
+                .iter()
+                .map(|(key, asset)| (key.clone(), asset.path.clone()))
+                .collect(),
+            integrity: assets
+                .iter()
+                .filter_map(|(key, asset)| Some((key.clone(), asset.integrity.clone()?)))
+                .collect(),
+        };
+        let json = serde_json::to_string_pretty(&manifest).map_err(std::io::Error::other);
+        json.and_then(|json| cache::write_file_atomic(&self.path, json.as_bytes()))
+            .map_err(|e| BuildError::io(&self.path, e))
+    }
+}
+
+impl From<ManifestFile> for BTreeMap<String, Asset> {
+    fn from(manifest: ManifestFile) -> Self {
+        let mut integrity = manifest.integrity;
+        manifest
+            .assets
+            .into_iter()
+            .map(|(key, path)| {
+                let integrity = integrity.remove(&key);
+                (key, Asset { path, integrity })
+            })
+            .collect()
+    }
+}
+
+// Manifest keys always use forward slashes, as they are part of URLs
+fn asset_key(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// The hash goes before the extension: styles/tailwind.css -> styles/tailwind.3f9a1c.css
+fn fingerprinted_path(path: &Path, hash: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match path.extension() {
+        Some(extension) => format!("{}.{}.{}", stem, hash, extension.to_string_lossy()),
+        None => format!("{}.{}", stem, hash),
+    };
+    path.with_file_name(file_name)
+}
+
+fn has_integrity(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| INTEGRITY_EXTENSIONS.contains(&extension))
+}
+
+// Subresource Integrity digest of a file: the base64 SHA-384 hash, prefixed with the algorithm
+fn integrity_digest(path: &Path) -> std::io::Result<String> {
+    let content = fs::read(path)?;
+    Ok(format!(
+        "sha384-{}",
+        BASE64.encode(Sha384::digest(&content))
+    ))
+}
+
+fn path_arg<'a>(function: &str, args: &'a HashMap<String, tera::Value>) -> tera::Result<&'a str> {
+    let path = args
+        .get("path")
+        .and_then(|value| value.as_str())
+        .ok_or_else(|| tera::Error::msg(format!("`{}`: missing `path` argument", function)))?;
+    Ok(path.trim_start_matches('/'))
+}
+
+// Tera function resolving the fingerprinted URL of an asset:
+//
+//   <link rel="stylesheet" href="{{ asset_url(path="styles/tailwind.css") }}" />
+//
+// The path is relative to the static folder (as the output path of optimized images, e.g.
+// "images/cover.webp"). Unknown assets fail the build rather than producing a broken link
+pub struct AssetUrl {
+    assets: Assets,
+}
+
+impl tera::Function for AssetUrl {
+    fn call(&self, args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+        let path = path_arg("asset_url", args)?;
+        let assets = self.assets.read().unwrap();
+        let asset = assets
+            .get(path)
+            .ok_or_else(|| tera::Error::msg(format!("`asset_url`: asset `{}` not found", path)))?;
+        Ok(tera::Value::String(format!("./static/{}", asset.path)))
+    }
+
+    fn is_safe(&self) -> bool {
+        true
+    }
+}
+
+// Tera function returning the integrity digest of a stylesheet or script:
+//
+//   <link rel="stylesheet" href="{{ asset_url(path="styles/tailwind.css") }}"
+//         integrity="{{ asset_integrity(path="styles/tailwind.css") }}" />
+//
+// The digest is empty for a generated asset that is yet to be built (the pages are rendered
+// again once it is)
+pub struct AssetIntegrity {
+    assets: Assets,
+}
+
+impl tera::Function for AssetIntegrity {
+    fn call(&self, args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+        let path = path_arg("asset_integrity", args)?;
+        if !has_integrity(path) {
+            return Err(tera::Error::msg(format!(
+                "`asset_integrity`: `{}` is not a stylesheet or script",
+                path
+            )));
+        }
+        let assets = self.assets.read().unwrap();
+        let asset = assets.get(path).ok_or_else(|| {
+            tera::Error::msg(format!("`asset_integrity`: asset `{}` not found", path))
+        })?;
+        Ok(tera::Value::String(
+            asset.integrity.clone().unwrap_or_default(),
+        ))
+    }
+
+    fn is_safe(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tera::Function;
+
+    // Build folder within the temporary directory, with the given files in its static folder
+    fn temp_output_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let output_dir =
+            std::env::temp_dir().join(format!("ssg-assets-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&output_dir);
+        for (file, content) in files {
+            let path = output_dir.join("static").join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        output_dir
+    }
+
+    fn path_args(path: &str) -> HashMap<String, tera::Value> {
+        HashMap::from([("path".to_string(), tera::Value::from(path))])
+    }
+
+    #[test]
+    fn fingerprint_goes_before_the_extension() {
+        assert_eq!(
+            fingerprinted_path(Path::new("styles/tailwind.css"), "3f9a1c"),
+            Path::new("styles/tailwind.3f9a1c.css")
+        );
+        assert_eq!(
+            fingerprinted_path(Path::new("LICENSE"), "3f9a1c"),
+            Path::new("LICENSE.3f9a1c")
+        );
+    }
+
+    #[test]
+    fn assets_resolve_to_their_fingerprinted_copies() {
+        let output_dir = temp_output_dir("fingerprint", &[("styles/site.css", "body {}")]);
+        let manifest = AssetManifest::load_or_default(&output_dir, &AssetConfig::default());
+        assert!(manifest.add(Path::new("styles/site.css")).unwrap());
+        assert!(manifest.add(Path::new("styles/generated.css")).unwrap());
+
+        let url = manifest
+            .asset_url()
+            .call(&path_args("/styles/site.css"))
+            .unwrap();
+        let url = url.as_str().unwrap();
+        assert!(url.starts_with("./static/styles/site.") && url.ends_with(".css"));
+        assert!(output_dir.join(&url[2..]).is_file());
+        // Files yet to be generated resolve to their plain name
+        assert_eq!(
+            manifest
+                .asset_url()
+                .call(&path_args("styles/generated.css"))
+                .unwrap(),
+            "./static/styles/generated.css"
+        );
+        assert!(manifest
+            .asset_url()
+            .call(&path_args("styles/missing.css"))
+            .is_err());
+        fs::remove_dir_all(&output_dir).unwrap();
+    }
+
+    #[test]
+    fn fingerprinting_can_be_disabled() {
+        let output_dir = temp_output_dir("plain", &[("images/cover.webp", "webp")]);
+        let manifest =
+            AssetManifest::load_or_default(&output_dir, &AssetConfig { fingerprint: false });
+        manifest.add(Path::new("images/cover.webp")).unwrap();
+        let rules = StaticRules::new(&Default::default()).unwrap();
+        assert_eq!(
+            manifest.resolve(&rules, Path::new("images/cover.png")),
+            "images/cover.webp"
+        );
+        assert_eq!(
+            fs::read_dir(output_dir.join("static/images"))
+                .unwrap()
+                .count(),
+            1
+        );
+        fs::remove_dir_all(&output_dir).unwrap();
+    }
+
+    #[test]
+    fn outdated_copies_are_pruned_once_the_content_changes() {
+        let output_dir = temp_output_dir("prune", &[("app.js", "one")]);
+        let manifest = AssetManifest::load_or_default(&output_dir, &AssetConfig::default());
+        manifest.add(Path::new("app.js")).unwrap();
+        manifest.save().unwrap();
+        let rules = StaticRules::new(&Default::default()).unwrap();
+        let first = output_dir
+            .join("static")
+            .join(manifest.resolve(&rules, Path::new("app.js")));
+
+        let manifest = AssetManifest::load_or_default(&output_dir, &AssetConfig::default());
+        manifest.add(Path::new("app.js")).unwrap();
+        assert!(!manifest.changed());
+        assert!(manifest.prune(false).unwrap().is_empty());
+
+        fs::write(output_dir.join("static/app.js"), "two").unwrap();
+        let manifest = AssetManifest::load_or_default(&output_dir, &AssetConfig::default());
+        manifest.add(Path::new("app.js")).unwrap();
+        assert!(manifest.changed());
+        assert_eq!(manifest.prune(true).unwrap(), std::slice::from_ref(&first));
+        assert!(first.exists());
+        assert_eq!(manifest.prune(false).unwrap(), std::slice::from_ref(&first));
+        assert!(!first.exists());
+        assert!(output_dir.join("static/app.js").exists());
+        fs::remove_dir_all(&output_dir).unwrap();
+    }
+
+    #[test]
+    fn stylesheets_and_scripts_have_an_integrity_digest() {
+        let output_dir = temp_output_dir(
+            "integrity",
+            &[
+                ("app.js", "alert(1);"),
+                ("styles/site.css", ""),
+                ("logo.svg", ""),
+            ],
+        );
+        let manifest = AssetManifest::load_or_default(&output_dir, &AssetConfig::default());
+        for path in [
+            "app.js",
+            "styles/site.css",
+            "logo.svg",
+            "styles/generated.css",
+        ] {
+            manifest.add(Path::new(path)).unwrap();
+        }
+        let integrity = |path: &str| manifest.asset_integrity().call(&path_args(path));
+
+        assert_eq!(
+            integrity("app.js").unwrap(),
+            "sha384-dnux3uAPxaf+IhCrFG1D/XVNzP1XLDNcn3Pe3jyxouEAoot5kfwC5u8rMwNhE5oi"
+        );
+        assert_eq!(
+            integrity("/styles/site.css").unwrap(),
+            "sha384-OLBgp1GsljhM2TJ+sbHjaiH9txEUvgdDTAzHv2P24donTt6/529l+9Ua0vFImLlb"
+        );
+        // Generated assets that are yet to be built have no digest yet
+        assert_eq!(integrity("styles/generated.css").unwrap(), "");
+        assert!(integrity("logo.svg").is_err());
+        assert!(integrity("missing.js").is_err());
+        fs::remove_dir_all(&output_dir).unwrap();
+    }
+
+    #[test]
+    fn integrity_digests_are_kept_in_the_manifest() {
+        let output_dir = temp_output_dir("manifest", &[("app.js", "alert(1);"), ("logo.svg", "")]);
+        let manifest = AssetManifest::load_or_default(&output_dir, &AssetConfig::default());
+        manifest.add(Path::new("app.js")).unwrap();
+        manifest.add(Path::new("logo.svg")).unwrap();
+        manifest.save().unwrap();
+
+        let saved: ManifestFile = serde_json::from_str(
+            &fs::read_to_string(output_dir.join("asset-manifest.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(saved.integrity.keys().collect::<Vec<_>>(), ["app.js"]);
+        let reloaded = AssetManifest::load_or_default(&output_dir, &AssetConfig::default());
+        assert_eq!(reloaded.previous, *manifest.assets.read().unwrap());
+        fs::remove_dir_all(&output_dir).unwrap();
+    }
+}
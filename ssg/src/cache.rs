@@ -1,103 +1,551 @@
-use log::info;
-use std::collections::HashMap;
-use std::path::PathBuf;
+use crate::error::BuildError;
+use log::{debug, info, warn};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
 use std::time::SystemTime;
 
 use serde::{Deserialize, Serialize};
 use std::fs::{self, File};
-use std::io::{self, Error, Read};
+use std::io::{self, Error};
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+// Version of the cache file format. Bump whenever FileCache or FileMetadata change so that
+// caches written by an older build are discarded instead of misread
+pub const CACHE_VERSION: u32 = 4;
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct FileCache {
+    // Caches written before the version field was added deserialize as version 0
+    #[serde(default)]
+    pub version: u32,
+    // Hash of the settings the outputs were generated with (see update_settings)
+    #[serde(default)]
+    pub settings: String,
     pub file_data: HashMap<PathBuf, FileMetadata>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Default for FileCache {
+    fn default() -> Self {
+        FileCache {
+            version: CACHE_VERSION,
+            settings: String::new(),
+            file_data: HashMap::new(),
+        }
+    }
+}
+
+// Files are keyed by their path relative to the project root (the directory of the
+// configuration YAML file), so the cache can be moved between machines (e.g. restored on the
+// GitHub Actions runner)
+// The outputs are the files generated from the source file within the build folder (also
+// relative to the project root), which are removed once the source file is deleted
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileMetadata {
     pub modified: SystemTime,
-    pub hash: Option<String>,
+    pub size: u64,
+    pub hash: String,
+    #[serde(default)]
+    pub outputs: Vec<PathBuf>,
+    // Hash of what the outputs depend on besides the file itself (e.g. the related posts of a
+    // post, which depend on the other posts)
+    #[serde(default)]
+    pub dependencies: String,
 }
 
 // Struct representing a specific cache that is initialized for the given JSON file (as specified
 // as the path buffer)
+// The cache is kept behind a mutex so files can be checked & updated from multiple threads
+// Changes are kept in memory, and written to the disk once at the end of the build (see save)
+// Files checked during the build are tracked, so entries for files that were deleted (or
+// renamed) can be pruned at the end of the build along with their outputs (see prune)
 pub struct CacheContext {
     pub path: PathBuf,
-    pub cache: FileCache,
+    pub root: PathBuf,
+    pub cache: Mutex<FileCache>,
+    dirty: AtomicBool,
+    seen: Mutex<HashSet<PathBuf>>,
+    replaced_outputs: Mutex<Vec<PathBuf>>,
 }
 
 impl CacheContext {
-    pub fn load_or_default(path: PathBuf) -> Result<Self, Error> {
+    // A cache file that cannot be read, is corrupt (e.g. truncated), or was written with a
+    // different format version is discarded, and every file is treated as changed
+    pub fn load_or_default(path: PathBuf, root: &Path) -> Self {
         let cache = if path.exists() {
-            load_cache(&path)
+            match load_cache(&path) {
+                Ok(cache) if cache.version == CACHE_VERSION => cache,
+                Ok(cache) => {
+                    warn!(
+                        "Discarding cache {:?} with version {} (expected version {})",
+                        path, cache.version, CACHE_VERSION
+                    );
+                    FileCache::default()
+                }
+                Err(e) => {
+                    warn!("Discarding unreadable cache {:?}: {}", path, e);
+                    FileCache::default()
+                }
+            }
         } else {
             FileCache::default()
         };
-        Ok(Self { path, cache })
-    }
-
-    // Based on the metadata modified date and the hash, return true if the file has changed. False
-    // otherwise
-    pub fn has_file_changed(&self, path: &PathBuf) -> Result<bool, Error> {
-        let current_metadata = compute_file_metadata(path)?;
-        if let Some(cached_metadata) = self.cache.file_data.get(path) {
-            if cached_metadata.modified == current_metadata.modified
-                || cached_metadata.hash == current_metadata.hash
-            {
-                return Ok(false); // If not modified, and hash is the same file has not changed
-            } else {
-                return Ok(true);
-            }
+        Self {
+            path,
+            root: root.to_path_buf(),
+            cache: Mutex::new(cache),
+            dirty: AtomicBool::new(false),
+            seen: Mutex::new(HashSet::new()),
+            replaced_outputs: Mutex::new(Vec::new()),
+        }
+    }
+
+    // Write the cache to the disk if it changed during the build
+    pub fn save(&self) -> Result<(), BuildError> {
+        if !self.dirty.load(Ordering::Relaxed) {
+            return Ok(());
         }
 
-        Ok(true)
+        let cache = self.cache.lock().unwrap();
+        save_cache(&cache, &self.path).map_err(|e| BuildError::io(&self.path, e))?;
+        self.dirty.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+
+    // Record the hash of the settings the outputs are generated with. Returns true if they
+    // differ from the previous build, in which case every output must be generated again (the
+    // unchanged source files alone do not tell): every file is then treated as changed, while
+    // its outputs are still kept track of
+    pub fn update_settings(&self, settings: &str) -> bool {
+        let mut cache = self.cache.lock().unwrap();
+        if cache.settings == settings {
+            return false;
+        }
+        cache.settings = settings.to_string();
+        for metadata in cache.file_data.values_mut() {
+            metadata.modified = SystemTime::UNIX_EPOCH;
+            metadata.hash.clear();
+        }
+        self.dirty.store(true, Ordering::Relaxed);
+        true
+    }
+
+    // Key of a file within the cache (relative to the project root where possible)
+    fn cache_key(&self, file_path: &Path) -> PathBuf {
+        file_path
+            .strip_prefix(&self.root)
+            .unwrap_or(file_path)
+            .to_path_buf()
     }
 
     // Checks if the file has changed (and updates the cache accordingly after updating)
     // Or does not perform the update (leaving the same results)
     // Returns true if the file was changed or false if otherwise
-    pub fn update_file_if_changed(&mut self, file_path: &PathBuf) -> io::Result<bool> {
-        let metadata = compute_file_metadata(file_path)?;
-        if self.has_file_changed(file_path)? {
-            self.cache
-                .file_data
-                .insert(file_path.to_path_buf(), metadata);
-            // Save the updated cache
-            println!(
-                "File path {:?} with cache path as {:?}",
-                file_path, self.path
-            );
-
-            let _ = save_cache(&self.cache, &self.path);
-            Ok(true)
+    //
+    // The modified time & size are checked first as a fast path: a file with both unchanged is
+    // not read at all. Otherwise, the content hash decides whether the file changed, so a file
+    // that was only touched (or restored with a new modified time) is not rebuilt, and a
+    // content change is never missed because of a preserved modified time alone
+    pub fn update_file_if_changed(&self, file_path: &Path) -> Result<bool, BuildError> {
+        let key = self.cache_key(file_path);
+        self.seen.lock().unwrap().insert(key.clone());
+        let io_error = |e| BuildError::io(file_path, e);
+        let file_metadata = fs::metadata(file_path).map_err(io_error)?;
+        let modified = file_metadata.modified().map_err(io_error)?;
+        let size = file_metadata.len();
+
+        let cached_metadata = self.cache.lock().unwrap().file_data.get(&key).cloned();
+        if let Some(cached_metadata) = &cached_metadata {
+            if cached_metadata.modified == modified && cached_metadata.size == size {
+                info!("Skipping unchanged file: {:?}", file_path);
+                return Ok(false);
+            }
+        }
+
+        // Hash outside of the lock, as this is the expensive part of the check
+        let hash = compute_file_hash(file_path).map_err(io_error)?;
+        let changed = cached_metadata
+            .as_ref()
+            .is_none_or(|cached_metadata| cached_metadata.hash != hash);
+
+        // Also record the new modified time & size of unchanged files, so the next check can
+        // take the fast path
+        let (outputs, dependencies) = cached_metadata
+            .map(|cached_metadata| (cached_metadata.outputs, cached_metadata.dependencies))
+            .unwrap_or_default();
+        self.cache.lock().unwrap().file_data.insert(
+            key,
+            FileMetadata {
+                modified,
+                size,
+                hash,
+                outputs,
+                dependencies,
+            },
+        );
+        self.dirty.store(true, Ordering::Relaxed);
+
+        if changed {
+            debug!("File path {:?} updated in cache {:?}", file_path, self.path);
         } else {
             info!("Skipping unchanged file: {:?}", file_path);
-            Ok(false)
         }
+        Ok(changed)
+    }
+
+    // Record the files generated from a source file (which must have been checked with
+    // update_file_if_changed first). Outputs from a previous build that are no longer generated
+    // (e.g. a page that was renamed through its frontmatter) are removed when pruning
+    pub fn record_outputs(&self, file_path: &Path, outputs: &[PathBuf]) {
+        let key = self.cache_key(file_path);
+        let outputs: Vec<PathBuf> = outputs
+            .iter()
+            .map(|output| self.cache_key(output))
+            .collect();
+
+        let mut cache = self.cache.lock().unwrap();
+        if let Some(metadata) = cache.file_data.get_mut(&key) {
+            if metadata.outputs != outputs {
+                self.replaced_outputs.lock().unwrap().extend(
+                    metadata
+                        .outputs
+                        .iter()
+                        .filter(|output| !outputs.contains(output))
+                        .cloned(),
+                );
+                metadata.outputs = outputs;
+                self.dirty.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    // Record the hash of the dependencies of a source file (which must have been checked with
+    // update_file_if_changed first). Returns true if they changed since the previous build
+    pub fn update_dependencies(&self, file_path: &Path, dependencies: &str) -> bool {
+        let key = self.cache_key(file_path);
+        let mut cache = self.cache.lock().unwrap();
+        let Some(metadata) = cache.file_data.get_mut(&key) else {
+            return true;
+        };
+        if metadata.dependencies == dependencies {
+            return false;
+        }
+        metadata.dependencies = dependencies.to_string();
+        self.dirty.store(true, Ordering::Relaxed);
+        true
+    }
+
+    // Remove the outputs of files that were not part of this build (deleted or renamed source
+    // files) along with their cache entries, and the outputs that are no longer generated.
+    // With dry_run, the files that would be removed are only reported, and the cache is left
+    // as-is. Returns the stale output files
+    pub fn prune(&self, dry_run: bool) -> Result<Vec<PathBuf>, BuildError> {
+        let seen = self.seen.lock().unwrap();
+        let mut cache = self.cache.lock().unwrap();
+
+        let mut stale_outputs: Vec<PathBuf> = if dry_run {
+            self.replaced_outputs.lock().unwrap().clone()
+        } else {
+            std::mem::take(&mut *self.replaced_outputs.lock().unwrap())
+        };
+        let stale_keys: Vec<PathBuf> = cache
+            .file_data
+            .keys()
+            .filter(|key| !seen.contains(*key))
+            .cloned()
+            .collect();
+        for key in &stale_keys {
+            info!("Source file {:?} no longer exists", key);
+            stale_outputs.extend(cache.file_data[key].outputs.iter().cloned());
+        }
+
+        // Outputs that are (still) generated by another source file are kept
+        let current_outputs: HashSet<&PathBuf> = cache
+            .file_data
+            .iter()
+            .filter(|(key, _)| seen.contains(*key))
+            .flat_map(|(_, metadata)| &metadata.outputs)
+            .collect();
+        stale_outputs.retain(|output| !current_outputs.contains(output));
+        stale_outputs.sort();
+        stale_outputs.dedup();
+
+        let stale_outputs: Vec<PathBuf> = stale_outputs
+            .iter()
+            .map(|output| self.root.join(output))
+            .filter(|output| output.exists())
+            .collect();
+        for output in &stale_outputs {
+            remove_stale_file(output, dry_run)?;
+        }
+
+        if !dry_run && !stale_keys.is_empty() {
+            for key in &stale_keys {
+                cache.file_data.remove(key);
+            }
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+
+        Ok(stale_outputs)
+    }
+}
+
+// Remove an output file that is no longer generated by the build (or only report it with
+// dry_run). The parent directory is removed as well if it is left empty
+pub fn remove_stale_file(path: &Path, dry_run: bool) -> Result<(), BuildError> {
+    if dry_run {
+        println!("Would remove stale output {}", path.display());
+        return Ok(());
+    }
+
+    info!("Removing stale output {:?}", path);
+    fs::remove_file(path).map_err(|e| BuildError::io(path, e))?;
+    if let Some(parent) = path.parent() {
+        // fails (and is ignored) if the directory is not empty
+        let _ = fs::remove_dir(parent);
     }
+    Ok(())
 }
 
 // Save the cache to the disk
-pub fn save_cache(cache: &FileCache, path: &PathBuf) -> Result<(), Error> {
+pub fn save_cache(cache: &FileCache, path: &Path) -> Result<(), Error> {
     let json = serde_json::to_string(cache)?;
-    fs::write(path, json)
+    write_file_atomic(path, json.as_bytes())
 }
 
-// Load the cache from the disk
-pub fn load_cache(path: &PathBuf) -> FileCache {
-    let data = fs::read_to_string(path).unwrap();
-    serde_json::from_str(&data).unwrap()
+// Write to a temporary file first, then rename it over the previous file so an interrupted
+// build never leaves a truncated file behind
+pub fn write_file_atomic(path: &Path, contents: &[u8]) -> Result<(), Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    fs::write(&temp_path, contents)?;
+    fs::rename(&temp_path, path)
 }
 
-// Compute the file metadata (uses blake3 hashing algorithm)
-pub fn compute_file_metadata(path: &PathBuf) -> Result<FileMetadata, Error> {
-    let metadata = fs::metadata(path)?;
-    let modified = metadata.modified()?; // last mod time in metadata
+// Load the cache from the disk
+pub fn load_cache(path: &Path) -> Result<FileCache, Error> {
+    let data = fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&data)?)
+}
 
-    // compute hash of file content
+// Compute the hash of the file content (uses blake3 hashing algorithm)
+pub fn compute_file_hash(path: &Path) -> Result<String, Error> {
     let mut file = File::open(path)?;
-    let mut content = Vec::new();
-    let _ = file.read_to_end(&mut content);
-    let hash = Some(blake3::hash(&content).to_hex().to_string());
+    let mut hasher = blake3::Hasher::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    // Empty project root within the temporary directory, unique to the test
+    fn temp_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("ssg-cache-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    fn load(root: &Path) -> CacheContext {
+        CacheContext::load_or_default(root.join("cache.json"), root)
+    }
+
+    #[test]
+    fn touched_file_with_the_same_content_is_unchanged() {
+        let root = temp_root("touched");
+        let source = root.join("page.md");
+        fs::write(&source, "content").unwrap();
+        let cache = load(&root);
+        assert!(cache.update_file_if_changed(&source).unwrap());
+
+        let modified = fs::metadata(&source).unwrap().modified().unwrap();
+        File::options()
+            .write(true)
+            .open(&source)
+            .unwrap()
+            .set_modified(modified + Duration::from_secs(60))
+            .unwrap();
+        assert!(!cache.update_file_if_changed(&source).unwrap());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn same_modified_time_with_a_different_size_is_changed() {
+        let root = temp_root("size");
+        let source = root.join("page.md");
+        fs::write(&source, "content").unwrap();
+        let cache = load(&root);
+        assert!(cache.update_file_if_changed(&source).unwrap());
+
+        let modified = fs::metadata(&source).unwrap().modified().unwrap();
+        fs::write(&source, "longer content").unwrap();
+        File::options()
+            .write(true)
+            .open(&source)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        assert!(cache.update_file_if_changed(&source).unwrap());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn keys_are_relative_to_the_root() {
+        let root = temp_root("keys");
+        fs::create_dir_all(root.join("content")).unwrap();
+        let source = root.join("content/page.md");
+        fs::write(&source, "content").unwrap();
+        let cache = load(&root);
+        cache.update_file_if_changed(&source).unwrap();
+        cache.record_outputs(&source, &[root.join("build/page.html")]);
+        cache.save().unwrap();
+
+        let saved = load_cache(&root.join("cache.json")).unwrap();
+        let metadata = &saved.file_data[Path::new("content/page.md")];
+        assert_eq!(metadata.outputs, vec![PathBuf::from("build/page.html")]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn corrupt_or_outdated_cache_starts_empty() {
+        let root = temp_root("corrupt");
+        let source = root.join("page.md");
+        fs::write(&source, "content").unwrap();
+        let cache = load(&root);
+        cache.update_file_if_changed(&source).unwrap();
+        cache.save().unwrap();
+        assert_eq!(load(&root).cache.lock().unwrap().file_data.len(), 1);
+
+        let cache_path = root.join("cache.json");
+        let json = fs::read_to_string(&cache_path).unwrap();
+        fs::write(&cache_path, &json[..json.len() / 2]).unwrap();
+        assert!(load(&root).cache.lock().unwrap().file_data.is_empty());
 
-    Ok(FileMetadata { modified, hash })
+        let outdated = json.replace(
+            &format!("\"version\":{}", CACHE_VERSION),
+            &format!("\"version\":{}", CACHE_VERSION - 1),
+        );
+        assert_ne!(outdated, json);
+        fs::write(&cache_path, outdated).unwrap();
+        let cache = load(&root);
+        assert!(cache.cache.lock().unwrap().file_data.is_empty());
+        assert!(cache.update_file_if_changed(&source).unwrap());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn changed_settings_mark_every_entry_as_changed() {
+        let root = temp_root("settings");
+        let sources = [root.join("a.md"), root.join("b.md")];
+        for source in &sources {
+            fs::write(source, "content").unwrap();
+        }
+        let cache = load(&root);
+        assert!(cache.update_settings("first"));
+        for source in &sources {
+            assert!(cache.update_file_if_changed(source).unwrap());
+        }
+        cache.save().unwrap();
+
+        let cache = load(&root);
+        assert!(!cache.update_settings("first"));
+        for source in &sources {
+            assert!(!cache.update_file_if_changed(source).unwrap());
+        }
+        assert!(cache.update_settings("second"));
+        for source in &sources {
+            assert!(cache.update_file_if_changed(source).unwrap());
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    // Cache of a previous build with two pages, of which only the first is part of the next one
+    fn cache_with_a_deleted_page(root: &Path) -> (CacheContext, PathBuf, PathBuf) {
+        let kept = root.join("kept.md");
+        let deleted = root.join("deleted.md");
+        let kept_output = root.join("build/kept.html");
+        let deleted_output = root.join("build/deleted.html");
+        fs::create_dir_all(root.join("build")).unwrap();
+        for (source, output) in [(&kept, &kept_output), (&deleted, &deleted_output)] {
+            fs::write(source, "content").unwrap();
+            fs::write(output, "html").unwrap();
+        }
+        let cache = load(root);
+        for (source, output) in [(&kept, &kept_output), (&deleted, &deleted_output)] {
+            cache.update_file_if_changed(source).unwrap();
+            cache.record_outputs(source, std::slice::from_ref(output));
+        }
+        cache.save().unwrap();
+        fs::remove_file(&deleted).unwrap();
+
+        let cache = load(root);
+        cache.update_file_if_changed(&kept).unwrap();
+        cache.record_outputs(&kept, std::slice::from_ref(&kept_output));
+        (cache, kept_output, deleted_output)
+    }
+
+    #[test]
+    fn prune_removes_the_outputs_of_unseen_sources() {
+        let root = temp_root("prune");
+        let (cache, kept_output, deleted_output) = cache_with_a_deleted_page(&root);
+
+        assert_eq!(cache.prune(false).unwrap(), vec![deleted_output.clone()]);
+        assert!(!deleted_output.exists());
+        assert!(kept_output.exists());
+        assert!(!cache
+            .cache
+            .lock()
+            .unwrap()
+            .file_data
+            .contains_key(Path::new("deleted.md")));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn prune_dry_run_keeps_the_outputs() {
+        let root = temp_root("dry-run");
+        let (cache, kept_output, deleted_output) = cache_with_a_deleted_page(&root);
+
+        assert_eq!(cache.prune(true).unwrap(), vec![deleted_output.clone()]);
+        assert!(deleted_output.exists());
+        assert!(kept_output.exists());
+        assert!(cache
+            .cache
+            .lock()
+            .unwrap()
+            .file_data
+            .contains_key(Path::new("deleted.md")));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn files_are_checked_from_multiple_threads() {
+        use rayon::prelude::*;
+
+        let root = temp_root("threads");
+        let sources: Vec<PathBuf> = (0..64)
+            .map(|index| root.join(format!("page-{}.md", index)))
+            .collect();
+        for source in &sources {
+            fs::write(source, source.to_string_lossy().as_bytes()).unwrap();
+        }
+        let cache = load(&root);
+        assert!(sources
+            .par_iter()
+            .all(|source| cache.update_file_if_changed(source).unwrap()));
+        cache.save().unwrap();
+
+        let cache = load(&root);
+        assert_eq!(cache.cache.lock().unwrap().file_data.len(), sources.len());
+        assert!(!sources
+            .par_iter()
+            .any(|source| cache.update_file_if_changed(source).unwrap()));
+        fs::remove_dir_all(&root).unwrap();
+    }
 }
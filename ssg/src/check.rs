@@ -0,0 +1,437 @@
+// Checks the generated site within the build folder for broken internal links and missing
+// assets. Every `href`/`src`/`srcset` URL in the generated HTML files that points within the
+// site must resolve to an existing output file (and anchor, if it has a fragment)
+use crate::parser;
+use log::info;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Result;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+// An attribute value found within an HTML file along with its tag, and the line & column (in
+// characters) it was found at. The column tells attributes apart within minified files, which
+// are a single line
+#[derive(Debug)]
+pub struct Attribute {
+    pub tag: String,
+    pub name: String,
+    pub value: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug)]
+pub struct BrokenLink {
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub target: String,
+    pub reason: String,
+}
+
+// Attributes holding the URLs of links and assets
+const LINK_ATTRIBUTES: [&str; 3] = ["href", "src", "srcset"];
+
+// Elements whose content is not HTML (and must not be scanned for attributes)
+const RAW_TEXT_ELEMENTS: [&str; 2] = ["script", "style"];
+
+// Extract the attributes of every tag within the HTML. Handles quoted and unquoted values (the
+// minified output drops quotes where possible), and skips comments, doctypes, and the content
+// of script/style elements
+pub fn extract_attributes(html: &str) -> Vec<Attribute> {
+    let bytes = html.as_bytes();
+    let mut attributes = Vec::new();
+    // byte offsets of the start of every line
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(html.match_indices('\n').map(|(offset, _)| offset + 1))
+        .collect();
+    let position = |offset: usize| {
+        let line = line_starts.partition_point(|start| *start <= offset);
+        let column = html[line_starts[line - 1]..offset].chars().count() + 1;
+        (line, column)
+    };
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'<' if html[i..].starts_with("<!--") => {
+                i = html[i..].find("-->").map_or(bytes.len(), |end| i + end + 3);
+            }
+            b'<' if bytes.get(i + 1).is_some_and(u8::is_ascii_alphabetic) => {
+                // tag name
+                let start = i + 1;
+                i = start;
+                while i < bytes.len()
+                    && !bytes[i].is_ascii_whitespace()
+                    && !b"/>".contains(&bytes[i])
+                {
+                    i += 1;
+                }
+                let tag_name = html[start..i].to_ascii_lowercase();
+
+                // attributes up until the end of the tag
+                while i < bytes.len() && bytes[i] != b'>' {
+                    if bytes[i].is_ascii_whitespace() || bytes[i] == b'/' {
+                        i += 1;
+                        continue;
+                    }
+
+                    let name_start = i;
+                    while i < bytes.len()
+                        && !bytes[i].is_ascii_whitespace()
+                        && !b"=>".contains(&bytes[i])
+                    {
+                        i += 1;
+                    }
+                    let name = html[name_start..i].to_ascii_lowercase();
+
+                    if bytes.get(i) != Some(&b'=') {
+                        continue; // attribute without a value
+                    }
+                    i += 1;
+
+                    let value = match bytes.get(i) {
+                        Some(&quote) if quote == b'"' || quote == b'\'' => {
+                            let value_start = i + 1;
+                            let value_end = html[value_start..]
+                                .find(quote as char)
+                                .map_or(bytes.len(), |end| value_start + end);
+                            i = (value_end + 1).min(bytes.len());
+                            &html[value_start..value_end]
+                        }
+                        _ => {
+                            let value_start = i;
+                            while i < bytes.len()
+                                && !bytes[i].is_ascii_whitespace()
+                                && bytes[i] != b'>'
+                            {
+                                i += 1;
+                            }
+                            &html[value_start..i]
+                        }
+                    };
+
+                    let (line, column) = position(name_start);
+                    attributes.push(Attribute {
+                        tag: tag_name.clone(),
+                        name,
+                        value: parser::decode_entities(value),
+                        line,
+                        column,
+                    });
+                }
+
+                // skip over the content of script/style elements
+                if RAW_TEXT_ELEMENTS.contains(&tag_name.as_str()) {
+                    let closing_tag = format!("</{}", tag_name);
+                    i = html[i..]
+                        .to_ascii_lowercase()
+                        .find(&closing_tag)
+                        .map_or(bytes.len(), |end| i + end);
+                }
+            }
+            _ => i += 1,
+        }
+    }
+
+    attributes
+}
+
+// Decode percent-encoded characters (e.g. %20 for a space within a file name)
+fn decode_percent(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = value
+            .get(i + 1..i + 3)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// URLs that do not point to a file within the site (other websites, email addresses, inline
+// data, etc.)
+fn is_external(url: &str) -> bool {
+    if url.starts_with("//") {
+        return true;
+    }
+    // a scheme (e.g. https:, mailto:, data:) comes before any path separator
+    match url.find(':') {
+        Some(colon) => !url[..colon].contains(['/', '?', '#']),
+        None => false,
+    }
+}
+
+// Resolve the path of a URL to a file within the build folder, following the same rules as
+// the static host (GitHub Pages): directories serve their index.html, and extensionless paths
+// serve the matching .html file
+fn resolve_path(output_dir: &Path, html_file: &Path, url_path: &str) -> Option<PathBuf> {
+    let url_path = decode_percent(url_path);
+    let base = match url_path.strip_prefix('/') {
+        Some(absolute_path) => output_dir.join(absolute_path),
+        None => html_file.parent().unwrap_or(output_dir).join(&url_path),
+    };
+
+    let html_file_name = base
+        .file_name()
+        .map(|name| format!("{}.html", name.to_string_lossy()));
+    let candidates = [
+        Some(base.clone()),
+        Some(base.join("index.html")),
+        html_file_name.map(|name| base.with_file_name(name)),
+    ];
+    candidates
+        .into_iter()
+        .flatten()
+        .find(|candidate| candidate.is_file())
+}
+
+// URLs of a link attribute: the value of href & src attributes, and every candidate of a
+// srcset attribute (URLs followed by an optional width or density descriptor)
+fn link_targets(attribute: &Attribute) -> Vec<&str> {
+    if attribute.name == "srcset" {
+        attribute
+            .value
+            .split(',')
+            .filter_map(|candidate| candidate.split_whitespace().next())
+            .collect()
+    } else {
+        vec![attribute.value.trim()]
+    }
+}
+
+// Collect the anchors (id attributes, and the names of <a> elements) within an HTML file
+fn collect_anchors(attributes: &[Attribute]) -> HashSet<String> {
+    attributes
+        .iter()
+        .filter(|attribute| {
+            attribute.name == "id" || (attribute.tag == "a" && attribute.name == "name")
+        })
+        .map(|attribute| attribute.value.clone())
+        .collect()
+}
+
+// Check every generated HTML file within the build folder. URLs starting with the base URL of
+// the site are treated as internal links
+pub fn check_site(output_dir: &Path, base_url: &str) -> Result<Vec<BrokenLink>> {
+    let mut html_files: Vec<PathBuf> = WalkDir::new(output_dir)
+        .into_iter()
+        .filter_map(std::result::Result::ok)
+        .filter(|entry| entry.path().extension().and_then(|e| e.to_str()) == Some("html"))
+        .map(|entry| entry.into_path())
+        .collect();
+    html_files.sort();
+
+    let mut attributes_by_file = HashMap::new();
+    for html_file in &html_files {
+        let html = fs::read_to_string(html_file)?;
+        attributes_by_file.insert(html_file.clone(), extract_attributes(&html));
+    }
+
+    let anchors_by_file: HashMap<&PathBuf, HashSet<String>> = attributes_by_file
+        .iter()
+        .map(|(html_file, attributes)| (html_file, collect_anchors(attributes)))
+        .collect();
+
+    let base_url = base_url.trim_end_matches('/');
+    let mut broken_links = Vec::new();
+    for html_file in &html_files {
+        info!("Checking links in {:?}", html_file);
+        for attribute in &attributes_by_file[html_file] {
+            let broken_link = |target: &str, reason: String| BrokenLink {
+                file: html_file.clone(),
+                line: attribute.line,
+                column: attribute.column,
+                target: target.to_string(),
+                reason,
+            };
+            if !LINK_ATTRIBUTES.contains(&attribute.name.as_str()) {
+                continue;
+            }
+            let targets = link_targets(attribute);
+            if targets.iter().all(|target| target.is_empty()) {
+                broken_links.push(broken_link(
+                    &attribute.value,
+                    format!("empty {} attribute", attribute.name),
+                ));
+                continue;
+            }
+
+            for original_target in targets {
+                let mut target = original_target;
+                if !base_url.is_empty() {
+                    if let Some(site_path) = target.strip_prefix(base_url) {
+                        if site_path.is_empty() || site_path.starts_with(['/', '?', '#']) {
+                            target = site_path;
+                        }
+                    }
+                }
+                if is_external(target) {
+                    continue;
+                }
+
+                // split off the fragment and query string
+                let (url, fragment) = match target.split_once('#') {
+                    Some((url, fragment)) => (url, Some(fragment)),
+                    None => (target, None),
+                };
+                let url_path = url.split('?').next().unwrap_or_default();
+
+                let resolved = if url_path.is_empty() {
+                    // "#anchor" (or the site root when the base URL was stripped)
+                    if url.is_empty() && fragment.is_some() {
+                        Some(html_file.clone())
+                    } else {
+                        resolve_path(output_dir, html_file, "/")
+                    }
+                } else {
+                    resolve_path(output_dir, html_file, url_path)
+                };
+
+                let Some(resolved) = resolved else {
+                    broken_links.push(broken_link(original_target, "file not found".to_string()));
+                    continue;
+                };
+
+                if let Some(fragment) = fragment.filter(|fragment| !fragment.is_empty()) {
+                    let fragment = decode_percent(fragment);
+                    let has_anchor = anchors_by_file
+                        .get(&resolved)
+                        .is_some_and(|anchors| anchors.contains(&fragment));
+                    if !has_anchor {
+                        broken_links.push(broken_link(
+                            original_target,
+                            format!("anchor #{} not found", fragment),
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(broken_links)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attribute<'a>(attributes: &'a [Attribute], name: &str) -> &'a Attribute {
+        attributes
+            .iter()
+            .find(|attribute| attribute.name == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn quoted_and_unquoted_attributes() {
+        let attributes = extract_attributes(
+            r#"<a href="/one" title='Two words' class=link data-x=a/b>text</a><input disabled>"#,
+        );
+        let values: Vec<(&str, &str, &str)> = attributes
+            .iter()
+            .map(|a| (a.tag.as_str(), a.name.as_str(), a.value.as_str()))
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                ("a", "href", "/one"),
+                ("a", "title", "Two words"),
+                ("a", "class", "link"),
+                ("a", "data-x", "a/b"),
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_entities() {
+        let attributes = extract_attributes(r#"<a href="/search?a=1&amp;b=&quot;2&quot;">x</a>"#);
+        assert_eq!(attributes[0].value, "/search?a=1&b=\"2\"");
+    }
+
+    #[test]
+    fn skips_comments_scripts_and_styles() {
+        let attributes = extract_attributes(
+            "<!-- <a href=\"/comment\"> -->\n<script>let a = '<a href=\"/script\">';</script>\n<style>a[href=\"/style\"] {}</style>\n<a href=\"/real\">",
+        );
+        assert_eq!(attributes.len(), 1);
+        assert_eq!(attributes[0].value, "/real");
+        assert_eq!(attributes[0].line, 4);
+    }
+
+    #[test]
+    fn lines_and_columns() {
+        let attributes =
+            extract_attributes("<html>\n<body>\n  <img alt=\"é\" src=a.png>\n<a\n href=/b>");
+        let src = attribute(&attributes, "src");
+        assert_eq!((src.line, src.column), (3, 16));
+        let href = attribute(&attributes, "href");
+        assert_eq!((href.line, href.column), (5, 2));
+
+        // minified pages are a single line
+        let attributes = extract_attributes("<p><a href=/a>a</a><a href=/b>b</a>");
+        let columns: Vec<(usize, usize)> = attributes.iter().map(|a| (a.line, a.column)).collect();
+        assert_eq!(columns, vec![(1, 7), (1, 23)]);
+    }
+
+    #[test]
+    fn srcset_candidates() {
+        let attributes = extract_attributes(
+            r#"<img srcset="./static/a-480.webp 480w, ./static/a-800.webp 800w,./static/a.webp">"#,
+        );
+        assert_eq!(
+            link_targets(&attributes[0]),
+            vec![
+                "./static/a-480.webp",
+                "./static/a-800.webp",
+                "./static/a.webp"
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_broken_links() {
+        let dir = std::env::temp_dir().join(format!("ssg-check-{}", std::process::id()));
+        fs::create_dir_all(dir.join("static")).unwrap();
+        fs::write(dir.join("static/a.webp"), "").unwrap();
+        fs::write(dir.join("about.html"), r#"<h1 id="top">About</h1>"#).unwrap();
+        fs::write(
+            dir.join("index.html"),
+            concat!(
+                r#"<a href="/about">ok</a><a href="https://example.com/about#top">ok</a>"#,
+                r#"<a href="/about#missing">anchor</a><a href="./missing.html">file</a>"#,
+                r#"<img srcset="./static/a.webp 1x, ./static/b.webp 2x"><a href="">empty</a>"#,
+                r#"<a href="mailto:someone@example.com">external</a>"#,
+            ),
+        )
+        .unwrap();
+
+        let broken_links = check_site(&dir, "https://example.com/").unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let broken: Vec<(&str, &str)> = broken_links
+            .iter()
+            .map(|link| (link.target.as_str(), link.reason.as_str()))
+            .collect();
+        assert_eq!(
+            broken,
+            vec![
+                ("/about#missing", "anchor #missing not found"),
+                ("./missing.html", "file not found"),
+                ("./static/b.webp", "file not found"),
+                ("", "empty href attribute"),
+            ]
+        );
+    }
+}
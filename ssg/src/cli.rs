@@ -0,0 +1,136 @@
+// Command line arguments for the static site generator. Parsed by hand to avoid pulling in
+// another dependency for a handful of flags
+use std::env;
+
+pub const USAGE: &str = "Usage: ssg [COMMAND] [OPTIONS]
+
+Commands:
+  build           Generate the site (default)
+  check           Check the generated site for broken internal links and missing
+                  assets
+  config          Validate the configuration file
+  watch           Generate the site, then regenerate it whenever a source file
+                  changes
+
+Options:
+  -j, --jobs <N>  Number of threads used to render pages and optimize images
+                  (defaults to the number of CPUs)
+  -e, --env <ENV> Configuration environment, layering config.<ENV>.yml over
+                  config.yml (defaults to SSG_ENV, or development)
+      --external  With check, also check the external links found in the content
+                  (requires network access)
+      --dry-run   Report the stale output files (left over from deleted or renamed
+                  sources) instead of removing them
+      --print     With config, print the effective configuration (with defaults
+                  and resolved paths)
+  -h, --help      Print this message";
+
+#[derive(Debug, Default, PartialEq)]
+pub enum Command {
+    #[default]
+    Build,
+    Check,
+    Config,
+    Watch,
+}
+
+#[derive(Debug, Default)]
+pub struct Args {
+    pub command: Command,
+    pub jobs: Option<usize>,
+    pub env: Option<String>,
+    pub dry_run: bool,
+    pub external: bool,
+    pub print: bool,
+    pub help: bool,
+}
+
+// Parse the arguments passed to the process (skipping the executable name)
+pub fn parse_args() -> Result<Args, String> {
+    parse(env::args().skip(1))
+}
+
+fn parse(mut raw_args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut args = Args::default();
+
+    while let Some(arg) = raw_args.next() {
+        // Support both `--flag value` and `--flag=value`
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                (flag.to_string(), Some(value.to_string()))
+            }
+            _ => (arg, None),
+        };
+        let mut value = |name: &str| {
+            inline_value
+                .clone()
+                .or_else(|| raw_args.next())
+                .ok_or_else(|| format!("Missing value for {}", name))
+        };
+
+        match flag.as_str() {
+            "-j" | "--jobs" => {
+                let jobs = value("--jobs")?;
+                args.jobs = match jobs.parse::<usize>() {
+                    Ok(jobs) if jobs > 0 => Some(jobs),
+                    _ => return Err(format!("Invalid number of jobs: {}", jobs)),
+                };
+            }
+            "-e" | "--env" => args.env = Some(value("--env")?),
+            "--dry-run" => args.dry_run = true,
+            "--external" => args.external = true,
+            "--print" => args.print = true,
+            "-h" | "--help" => args.help = true,
+            "build" => args.command = Command::Build,
+            "check" => args.command = Command::Check,
+            "config" => args.command = Command::Config,
+            "watch" => args.command = Command::Watch,
+            _ => return Err(format!("Unknown argument: {}", flag)),
+        }
+    }
+
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_strs(args: &[&str]) -> Result<Args, String> {
+        parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn jobs_accept_separate_and_inline_values() {
+        assert_eq!(parse_strs(&[]).unwrap().jobs, None);
+        assert_eq!(parse_strs(&["--jobs", "2"]).unwrap().jobs, Some(2));
+        assert_eq!(parse_strs(&["-j", "3"]).unwrap().jobs, Some(3));
+        assert_eq!(parse_strs(&["--jobs=4"]).unwrap().jobs, Some(4));
+    }
+
+    #[test]
+    fn jobs_reject_invalid_values() {
+        for args in [&["--jobs", "0"][..], &["--jobs=-1"], &["-j", "many"]] {
+            assert!(parse_strs(args)
+                .unwrap_err()
+                .starts_with("Invalid number of jobs"));
+        }
+        assert_eq!(
+            parse_strs(&["--jobs"]).unwrap_err(),
+            "Missing value for --jobs"
+        );
+    }
+
+    #[test]
+    fn commands_and_flags_in_any_order() {
+        let args = parse_strs(&["--dry-run", "watch", "--env=production", "-j", "2"]).unwrap();
+        assert_eq!(args.command, Command::Watch);
+        assert_eq!(args.env.as_deref(), Some("production"));
+        assert_eq!(args.jobs, Some(2));
+        assert!(args.dry_run);
+        assert_eq!(
+            parse_strs(&["deploy"]).unwrap_err(),
+            "Unknown argument: deploy"
+        );
+    }
+}
@@ -0,0 +1,209 @@
+// Precompressed copies of the build outputs (e.g. index.html.gz & index.html.br next to
+// index.html), for hosts that serve them in place of the original files. The outputs are
+// tracked in their own cache, so unchanged outputs are not compressed again, and the compressed
+// copies are removed along with their output
+use crate::cache::CacheContext;
+use crate::config::Config;
+use crate::error::BuildError;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::info;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+// Extensions of the outputs that are compressed (text formats)
+const COMPRESSED_EXTENSIONS: [&str; 5] = ["html", "css", "js", "xml", "json"];
+
+const COMPRESSION_CACHE: &str = "compression-cache.json";
+
+// Compression settings from the configuration YAML (the `compression` section)
+// - enabled: write the compressed copies (disabling it removes the existing ones)
+// - gzip/brotli: formats to write (.gz and .br)
+// - min_size: outputs smaller than this (in bytes) are left uncompressed, as the compressed
+//   copy would barely be smaller (if at all)
+// - gzip_level: 0 to 9, brotli_level: 0 to 11
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CompressionConfig {
+    pub enabled: bool,
+    pub gzip: bool,
+    pub brotli: bool,
+    pub min_size: u64,
+    pub gzip_level: u32,
+    pub brotli_level: u32,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        CompressionConfig {
+            enabled: false,
+            gzip: true,
+            brotli: true,
+            min_size: 1024,
+            gzip_level: 9,
+            brotli_level: 11,
+        }
+    }
+}
+
+impl CompressionConfig {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.gzip_level > 9 {
+            errors.push(format!(
+                "`compression.gzip_level` must be between 0 and 9 (got {})",
+                self.gzip_level
+            ));
+        }
+        if self.brotli_level > 11 {
+            errors.push(format!(
+                "`compression.brotli_level` must be between 0 and 11 (got {})",
+                self.brotli_level
+            ));
+        }
+        errors
+    }
+}
+
+// Files written by the build for itself (the caches and the asset manifest) rather than for the
+// site
+fn is_build_metadata(path: &Path, output_dir: &Path) -> bool {
+    let name = path.file_name().and_then(OsStr::to_str).unwrap_or_default();
+    path.parent() == Some(output_dir)
+        && (name.ends_with("cache.json") || name == "asset-manifest.json")
+}
+
+fn compressible_outputs(output_dir: &Path) -> Vec<PathBuf> {
+    WalkDir::new(output_dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.into_path())
+        .filter(|path| {
+            path.extension()
+                .and_then(OsStr::to_str)
+                .is_some_and(|extension| COMPRESSED_EXTENSIONS.contains(&extension))
+        })
+        .filter(|path| !is_build_metadata(path, output_dir))
+        .collect()
+}
+
+// The compressed copy of a file, next to it (e.g. index.html -> index.html.gz)
+fn sibling(path: &Path, extension: &str) -> PathBuf {
+    let mut file_name = path.as_os_str().to_owned();
+    file_name.push(".");
+    file_name.push(extension);
+    PathBuf::from(file_name)
+}
+
+fn write_gzip(content: &[u8], output_path: &Path, level: u32) -> io::Result<()> {
+    let mut encoder = GzEncoder::new(File::create(output_path)?, Compression::new(level));
+    encoder.write_all(content)?;
+    encoder.finish()?;
+    Ok(())
+}
+
+fn write_brotli(content: &[u8], output_path: &Path, level: u32) -> io::Result<()> {
+    // 4 KiB buffer, and a 4 MiB window (the default of the brotli CLI)
+    let mut encoder = brotli::CompressorWriter::new(File::create(output_path)?, 4096, level, 22);
+    encoder.write_all(content)?;
+    encoder.flush()
+}
+
+// Compress a single output, unless it is unchanged since the previous build (and its
+// compressed copies still exist). Returns whether it was compressed
+fn compress_output(
+    path: &Path,
+    config: &CompressionConfig,
+    cache_context: &CacheContext,
+) -> Result<bool, BuildError> {
+    let size = fs::metadata(path)
+        .map_err(|e| BuildError::io(path, e))?
+        .len();
+    let gzip_path = sibling(path, "gz");
+    let brotli_path = sibling(path, "br");
+    let mut outputs = Vec::new();
+    if size >= config.min_size {
+        if config.gzip {
+            outputs.push(gzip_path.clone());
+        }
+        if config.brotli {
+            outputs.push(brotli_path.clone());
+        }
+    }
+
+    let changed = cache_context.update_file_if_changed(path)?;
+    cache_context.record_outputs(path, &outputs);
+    if outputs.is_empty() || (!changed && outputs.iter().all(|output| output.exists())) {
+        return Ok(false);
+    }
+
+    let content = fs::read(path).map_err(|e| BuildError::io(path, e))?;
+    if outputs.contains(&gzip_path) {
+        write_gzip(&content, &gzip_path, config.gzip_level)
+            .map_err(|e| BuildError::io(&gzip_path, e))?;
+    }
+    if outputs.contains(&brotli_path) {
+        write_brotli(&content, &brotli_path, config.brotli_level)
+            .map_err(|e| BuildError::io(&brotli_path, e))?;
+    }
+    Ok(true)
+}
+
+// Write the compressed copies of the outputs (once everything else is written to the build
+// folder). When compression is disabled, the copies of previous builds are removed
+pub fn compress_outputs(config: &Config, dry_run: bool) -> Result<(), Vec<BuildError>> {
+    let output_dir = &config.paths.output_dir;
+    let cache_path = output_dir.join(COMPRESSION_CACHE);
+    let compression = &config.compression;
+    if !compression.enabled && !cache_path.exists() {
+        return Ok(());
+    }
+    let cache_context = CacheContext::load_or_default(cache_path, &config.paths.root_dir);
+    // The compressed copies depend on the compression settings (e.g. a new level, or brotli
+    // being enabled), so every output is compressed again when they change
+    let settings = serde_json::to_string(compression).unwrap_or_default();
+    let settings = blake3::hash(settings.as_bytes()).to_hex().to_string();
+    if cache_context.update_settings(&settings) && compression.enabled {
+        info!("Compression settings changed. Compressing every output");
+    }
+
+    let mut errors = Vec::new();
+    if compression.enabled {
+        let results: Vec<Result<bool, BuildError>> = compressible_outputs(output_dir)
+            .par_iter()
+            .map(|path| compress_output(path, compression, &cache_context))
+            .collect();
+        let mut compressed = 0;
+        for result in results {
+            match result {
+                Ok(true) => compressed += 1,
+                Ok(false) => {}
+                Err(e) => errors.push(e),
+            }
+        }
+        info!("Compressed {} output file(s)", compressed);
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    // Outputs that were not seen (removed, or every output when compression is disabled) lose
+    // their compressed copies
+    let stale_files = cache_context.prune(dry_run).map_err(|e| vec![e])?;
+    info!("Removed {} stale compressed file(s)", stale_files.len());
+    if dry_run {
+        return Ok(());
+    }
+    if compression.enabled {
+        cache_context.save().map_err(|e| vec![e])
+    } else {
+        let cache_path = &cache_context.path;
+        fs::remove_file(cache_path).map_err(|e| vec![BuildError::io(cache_path, e)])
+    }
+}
@@ -0,0 +1,600 @@
+// Loads & validates the configuration YAML file. Unknown (e.g. misspelled) keys are reported
+// with a suggestion rather than silently ignored, and the configured directories are checked
+// before the build starts. Optional sections and keys fall back to the defaults below
+//
+// The configuration is layered:
+// 1. config.yml
+// 2. config.<environment>.yml next to it (e.g. config.production.yml), where the environment is
+//    selected with --env or the SSG_ENV environment variable (development by default)
+// 3. SSG_<SECTION>__<KEY> environment variables for individual keys (e.g. SSG_BUILD__CACHE=true)
+use crate::assets::AssetConfig;
+use crate::compress::CompressionConfig;
+use crate::css::{CssConfig, Generator};
+use crate::error::BuildError;
+use crate::linkcheck::LinkCheckConfig;
+use crate::path;
+use crate::processors::{self, Processor};
+use crate::related::RelatedConfig;
+use crate::resources::{StaticConfig, StaticRules};
+use crate::search::SearchConfig;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_ENVIRONMENT: &str = "development";
+const ENVIRONMENT_VARIABLE: &str = "SSG_ENV";
+// Prefix of the environment variables overriding individual keys. Nested keys are separated by
+// a double underscore, as keys themselves contain underscores (e.g. SSG_BUILD__MINIFY_HTML)
+const OVERRIDE_PREFIX: &str = "SSG_";
+const OVERRIDE_SEPARATOR: &str = "__";
+
+// Retrieve configuration information from the configuration YAML file
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Config {
+    pub metadata: SiteMetadata,
+    #[serde(default)]
+    pub paths: Paths,
+    #[serde(default)]
+    pub build: Build,
+    #[serde(default)]
+    pub content: Content,
+    #[serde(default, rename = "static")]
+    pub static_files: StaticConfig,
+    #[serde(default)]
+    pub css: CssConfig,
+    #[serde(default)]
+    pub assets: AssetConfig,
+    #[serde(default)]
+    pub compression: CompressionConfig,
+    #[serde(default)]
+    pub search: SearchConfig,
+    #[serde(default)]
+    pub related: RelatedConfig,
+    #[serde(default)]
+    pub link_check: LinkCheckConfig,
+    #[serde(default)]
+    pub processors: Vec<Processor>,
+    // Environment the configuration was loaded for. Not part of the YAML file
+    #[serde(skip)]
+    pub environment: String,
+}
+
+// Metadata retrieved from the configuration YAML file. Exposed to the templates as `site`,
+// along with params: free-form values for the templates (e.g. social links or analytics IDs)
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SiteMetadata {
+    pub base_url: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub author: String,
+    #[serde(default)]
+    pub description: String,
+    // Default image for link previews (relative to the static folder, or an absolute URL)
+    #[serde(default)]
+    pub image: Option<String>,
+    #[serde(default)]
+    pub params: BTreeMap<String, tera::Value>,
+}
+
+// Paths for content, template, output build, and the static resourees. Specified as the path
+// relative to the configuration YAML file. NOT the Rust project directories
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Paths {
+    pub content_dir: PathBuf,
+    pub template_dir: PathBuf,
+    pub output_dir: PathBuf,
+    pub static_dir: PathBuf,
+    // Directory of the configuration YAML file (the project root), and the file itself. Not
+    // part of the YAML file
+    #[serde(skip)]
+    pub root_dir: PathBuf,
+    #[serde(skip)]
+    pub config_file: PathBuf,
+}
+
+impl Default for Paths {
+    fn default() -> Self {
+        Paths {
+            content_dir: PathBuf::from("content"),
+            template_dir: PathBuf::from("templates"),
+            output_dir: PathBuf::from("build"),
+            static_dir: PathBuf::from("static"),
+            root_dir: PathBuf::new(),
+            config_file: PathBuf::new(),
+        }
+    }
+}
+
+// Build configuration specifications from configuration YAML.
+// TODO: Implement sitemap generation feature
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Build {
+    pub minify_html: bool,
+    // Minify the stylesheets & scripts copied from the static folder, and the inline CSS of the
+    // generated HTML (inline scripts are minified along with the HTML)
+    pub minify_css: bool,
+    pub minify_js: bool,
+    pub generate_sitemap: bool,
+    pub cache: bool,
+    // Render the pages marked as drafts in their frontmatter (`draft: true`)
+    pub drafts: bool,
+}
+
+impl Default for Build {
+    fn default() -> Self {
+        Build {
+            minify_html: true,
+            minify_css: true,
+            minify_js: true,
+            generate_sitemap: false,
+            cache: true,
+            drafts: false,
+        }
+    }
+}
+
+// Settings for the content of the pages
+// - words_per_minute: reading speed used for the reading time of the pages
+// - summary_words: length of the summary of pages without a `<!-- more -->` marker
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Content {
+    pub words_per_minute: u32,
+    pub summary_words: usize,
+}
+
+impl Default for Content {
+    fn default() -> Self {
+        Content {
+            words_per_minute: 200,
+            summary_words: 50,
+        }
+    }
+}
+
+// Resolves all directory paths relative to the configuration YAML file to
+// to change to absbolute paths in the project
+fn reconcile_configuration_directory_paths(base_path: &Path, config: Paths) -> Paths {
+    Paths {
+        content_dir: base_path.join(config.content_dir),
+        template_dir: base_path.join(config.template_dir),
+        output_dir: base_path.join(config.output_dir),
+        static_dir: base_path.join(config.static_dir),
+        root_dir: base_path.to_path_buf(),
+        config_file: config.config_file,
+    }
+}
+
+// Location of the configuration YAML file (CONFIG_PATH, or config.yml in the parent directory)
+pub fn config_path() -> PathBuf {
+    path::resolve_environment_variable_path("CONFIG_PATH", "../config.yml")
+}
+
+// Grabs the configuration file relative to the location in the CONFIG_PATH environment variable
+// The environment (--env) takes precedence over the SSG_ENV environment variable
+// Every problem with the configuration is reported at once
+pub fn retrieve_configuration(environment: Option<&str>) -> Result<Config, Vec<BuildError>> {
+    info!("Retrieving config file");
+
+    let config_path = config_path();
+    let config_dir = config_path.parent().unwrap_or_else(|| Path::new("."));
+    debug!("{:?}", config_path);
+
+    let selected_environment = environment
+        .map(str::to_string)
+        .or_else(|| env::var(ENVIRONMENT_VARIABLE).ok());
+    let environment = selected_environment
+        .clone()
+        .unwrap_or_else(|| DEFAULT_ENVIRONMENT.to_string());
+    info!("Environment: {}", environment);
+
+    // Every layer (the file, its overlay and every override) is kept along with its source, so
+    // that unknown keys are reported where they were set
+    let mut layers = vec![(
+        config_path.clone(),
+        read_yaml(&config_path).map_err(|e| vec![e])?,
+    )];
+    let overlay_path = overlay_path(&config_path, &environment);
+    if overlay_path.exists() {
+        info!("Applying config overlay {:?}", overlay_path);
+        let overlay = read_yaml(&overlay_path).map_err(|e| vec![e])?;
+        layers.push((overlay_path, overlay));
+    } else if selected_environment.is_some() {
+        // Only the default environment may go without an overlay
+        return Err(vec![BuildError::io(
+            &overlay_path,
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!(
+                    "no configuration file for the `{}` environment",
+                    environment
+                ),
+            ),
+        )]);
+    }
+    layers.extend(
+        overrides(env::vars())?
+            .into_iter()
+            .map(|(name, overlay)| (PathBuf::from(name), overlay)),
+    );
+
+    let (mut config, mut errors) = load_yaml_config(&config_path, &layers).map_err(|e| vec![e])?;
+    config.environment = environment;
+    config.paths.config_file = config_path.clone();
+    config.paths = reconcile_configuration_directory_paths(config_dir, config.paths);
+    errors.extend(validate(&config));
+    debug!("{:?}", config);
+
+    if errors.is_empty() {
+        Ok(config)
+    } else {
+        Err(errors)
+    }
+}
+
+// Reading YAML configuration files
+fn read_yaml(file_path: &Path) -> Result<Value, BuildError> {
+    let content = fs::read_to_string(file_path).map_err(|e| BuildError::io(file_path, e))?;
+    serde_yaml::from_str(&content).map_err(|e| BuildError::config(file_path, &e))
+}
+
+// config.<environment>.yml next to the configuration file
+fn overlay_path(config_path: &Path, environment: &str) -> PathBuf {
+    let stem = config_path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    let extension = config_path
+        .extension()
+        .map_or("yml".into(), |extension| extension.to_string_lossy());
+    config_path.with_file_name(format!("{}.{}.{}", stem, environment, extension))
+}
+
+// Merge the overlay into the base configuration. Mappings are merged key by key, any other
+// value (including lists) replaces the base value
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Mapping(base), Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(base_value) => merge(base_value, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+// Override individual keys with SSG_<SECTION>__<KEY> environment variables. Values are parsed
+// as YAML, so `true`, `10` and `[a, b]` become a boolean, a number and a list. Returns the
+// overlay of every variable, along with its name. Variables with an empty key (e.g.
+// SSG_BUILD__ or SSG_BUILD____CACHE) are rejected
+fn overrides(
+    variables: impl Iterator<Item = (String, String)>,
+) -> Result<Vec<(String, Value)>, Vec<BuildError>> {
+    let mut overlays = Vec::new();
+    let mut errors = Vec::new();
+    for (name, value) in variables {
+        let Some(key_path) = name.strip_prefix(OVERRIDE_PREFIX) else {
+            continue;
+        };
+        if !key_path.contains(OVERRIDE_SEPARATOR) {
+            continue;
+        }
+        if key_path.split(OVERRIDE_SEPARATOR).any(str::is_empty) {
+            errors.push(BuildError::Config {
+                path: PathBuf::from(&name),
+                line: None,
+                message: format!(
+                    "malformed override: expected {}<SECTION>{}<KEY> (e.g. SSG_BUILD__MINIFY_HTML)",
+                    OVERRIDE_PREFIX, OVERRIDE_SEPARATOR
+                ),
+            });
+            continue;
+        }
+
+        info!("Overriding config key from {}", name);
+        let value = serde_yaml::from_str(&value).unwrap_or(Value::String(value));
+        let mut overlay = value;
+        for key in key_path.rsplit(OVERRIDE_SEPARATOR) {
+            let mut mapping = serde_yaml::Mapping::new();
+            mapping.insert(Value::String(key.to_ascii_lowercase()), overlay);
+            overlay = Value::Mapping(mapping);
+        }
+        overlays.push((name, overlay));
+    }
+    if errors.is_empty() {
+        Ok(overlays)
+    } else {
+        Err(errors)
+    }
+}
+
+// Merge the layers into the configuration. Along with it, returns an error for every unknown
+// key, reported against the layer that set it (the file or the environment variable)
+fn load_yaml_config(
+    file_path: &Path,
+    layers: &[(PathBuf, Value)],
+) -> Result<(Config, Vec<BuildError>), BuildError> {
+    let mut raw = Value::Null;
+    for (_, layer) in layers {
+        merge(&mut raw, layer.clone());
+    }
+    let config: Config =
+        serde_yaml::from_value(raw).map_err(|e| BuildError::config(file_path, &e))?;
+
+    // Every key the configuration knows about is part of the serialized configuration, so any
+    // key of a layer missing from it was ignored during deserialization
+    let known = serde_yaml::to_value(&config).map_err(|e| BuildError::config(file_path, &e))?;
+    let errors = layers
+        .iter()
+        .flat_map(|(source, layer)| {
+            unknown_keys(layer, &known, "")
+                .into_iter()
+                .map(|(key, suggestion)| BuildError::Config {
+                    path: source.clone(),
+                    line: None,
+                    message: match suggestion {
+                        Some(suggestion) => {
+                            format!("unknown key `{}` (did you mean `{}`?)", key, suggestion)
+                        }
+                        None => format!("unknown key `{}`", key),
+                    },
+                })
+        })
+        .collect();
+
+    Ok((config, errors))
+}
+
+// Check the values that deserialize fine but cannot be used for a build
+fn validate(config: &Config) -> Vec<BuildError> {
+    let config_error = |message: String| BuildError::Config {
+        path: config.paths.config_file.clone(),
+        line: None,
+        message,
+    };
+    let mut errors = Vec::new();
+
+    // The output directory is created by the build
+    let paths = &config.paths;
+    for (key, dir) in [
+        ("content_dir", &paths.content_dir),
+        ("template_dir", &paths.template_dir),
+        ("static_dir", &paths.static_dir),
+    ] {
+        if !dir.is_dir() {
+            errors.push(config_error(format!(
+                "`paths.{}`: directory {:?} does not exist",
+                key, dir
+            )));
+        }
+    }
+
+    if config.content.words_per_minute == 0 {
+        errors.push(config_error(
+            "`content.words_per_minute` must be greater than 0".to_string(),
+        ));
+    }
+    if config.content.summary_words == 0 {
+        errors.push(config_error(
+            "`content.summary_words` must be greater than 0".to_string(),
+        ));
+    }
+    if let Err(e) = StaticRules::new(&config.static_files) {
+        errors.push(config_error(format!("invalid `static` pattern: {}", e)));
+    }
+    if let Err(e) = Generator::new(&config.css.theme) {
+        errors.push(config_error(format!("invalid `css.theme`: {}", e)));
+    }
+    for e in config.search.validate() {
+        errors.push(config_error(e));
+    }
+    for e in config.compression.validate() {
+        errors.push(config_error(e));
+    }
+    for e in processors::validate(&config.processors, &paths.root_dir) {
+        errors.push(config_error(format!("invalid `processors`: {}", e)));
+    }
+    errors
+}
+
+// Keys of the raw YAML file that are missing from the known keys (dotted path, and the closest
+// known key at the same level if it looks like a typo)
+fn unknown_keys(raw: &Value, known: &Value, prefix: &str) -> Vec<(String, Option<String>)> {
+    let mut keys = Vec::new();
+    match (raw, known) {
+        (Value::Mapping(raw), Value::Mapping(known)) => {
+            for (key, value) in raw {
+                let name = match key {
+                    Value::String(name) => name.clone(),
+                    key => serde_yaml::to_string(key)
+                        .unwrap_or_default()
+                        .trim()
+                        .to_string(),
+                };
+                let key_path = if prefix.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}.{}", prefix, name)
+                };
+                match known.get(key) {
+                    Some(known_value) => keys.extend(unknown_keys(value, known_value, &key_path)),
+                    None => {
+                        let suggestion = known
+                            .keys()
+                            .filter_map(Value::as_str)
+                            .map(|known_name| (edit_distance(&name, known_name), known_name))
+                            .filter(|(distance, _)| *distance <= (name.len() / 3).max(2))
+                            .min()
+                            .map(|(_, known_name)| known_name.to_string());
+                        keys.push((key_path, suggestion));
+                    }
+                }
+            }
+        }
+        (Value::Sequence(raw), Value::Sequence(known)) => {
+            for (index, (value, known_value)) in raw.iter().zip(known).enumerate() {
+                keys.extend(unknown_keys(
+                    value,
+                    known_value,
+                    &format!("{}[{}]", prefix, index),
+                ));
+            }
+        }
+        _ => {}
+    }
+    keys
+}
+
+// Levenshtein distance between two keys
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn yaml(text: &str) -> Value {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    // Unknown keys of a configuration file (which must deserialize)
+    fn unknown(text: &str) -> Vec<(String, Option<String>)> {
+        let raw = yaml(text);
+        let config: Config = serde_yaml::from_value(raw.clone()).unwrap();
+        unknown_keys(&raw, &serde_yaml::to_value(&config).unwrap(), "")
+    }
+
+    #[test]
+    fn nested_unknown_key_has_its_dotted_path() {
+        assert_eq!(
+            unknown("metadata: {base_url: x}\ncss: {minify: true, theme: {colours: {}}}"),
+            vec![("css.theme.colours".to_string(), Some("colors".to_string()))]
+        );
+    }
+
+    #[test]
+    fn close_typo_gets_a_suggestion() {
+        assert_eq!(
+            unknown("metadata: {base_url: x}\nbuild: {cahce: true}\nbuidl: {}"),
+            vec![
+                ("build.cahce".to_string(), Some("cache".to_string())),
+                ("buidl".to_string(), Some("build".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn distant_typo_gets_no_suggestion() {
+        assert_eq!(
+            unknown("metadata: {base_url: x}\nbuild: {incremental: true}"),
+            vec![("build.incremental".to_string(), None)]
+        );
+    }
+
+    fn variables(variables: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+        variables
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn overlay_replaces_scalars_and_merges_maps() {
+        let mut base = yaml(
+            "build: {cache: false, drafts: false}
+search: {stop_words: [a, b]}",
+        );
+        merge(
+            &mut base,
+            yaml(
+                "build: {cache: true}
+search: {stop_words: [c]}
+css: {minify: false}",
+            ),
+        );
+        assert_eq!(
+            base,
+            yaml(
+                "build: {cache: true, drafts: false}
+search: {stop_words: [c]}
+css: {minify: false}"
+            )
+        );
+    }
+
+    #[test]
+    fn override_sets_a_nested_key_parsed_as_yaml() {
+        let overlays = overrides(variables(&[
+            ("SSG_BUILD__MINIFY_HTML", "false"),
+            ("SSG_RELATED__LIMIT", "5"),
+            ("SSG_METADATA__TITLE", "My site"),
+            ("SSG_ENV", "production"),
+            ("HOME", "/root"),
+        ]))
+        .unwrap();
+        assert_eq!(
+            overlays,
+            vec![
+                (
+                    "SSG_BUILD__MINIFY_HTML".to_string(),
+                    yaml("build: {minify_html: false}")
+                ),
+                (
+                    "SSG_RELATED__LIMIT".to_string(),
+                    yaml("related: {limit: 5}")
+                ),
+                (
+                    "SSG_METADATA__TITLE".to_string(),
+                    yaml("metadata: {title: My site}")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_override_is_rejected() {
+        for name in ["SSG_BUILD__", "SSG___CACHE", "SSG_BUILD____CACHE"] {
+            let errors = overrides(variables(&[(name, "true")])).unwrap_err();
+            assert_eq!(errors.len(), 1, "{}", name);
+            assert!(
+                matches!(&errors[0], BuildError::Config { path, .. } if path == Path::new(name))
+            );
+        }
+    }
+
+    #[test]
+    fn words_per_minute_must_be_positive() {
+        let config: Config =
+            serde_yaml::from_str("metadata: {base_url: x}\ncontent: {words_per_minute: 0}")
+                .unwrap();
+        let messages: Vec<String> = validate(&config).iter().map(ToString::to_string).collect();
+        assert!(messages
+            .iter()
+            .any(|message| message.ends_with("`content.words_per_minute` must be greater than 0")));
+    }
+}
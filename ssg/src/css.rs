@@ -0,0 +1,1807 @@
+// Builds the stylesheet of the site. The native engine generates the Tailwind utility classes
+// used by the generated pages & templates (a subset of Tailwind v3 with its default theme), so
+// that the build does not depend on Node. The Tailwind CLI can still be used instead when it is
+// installed (`css.engine: tailwind`), falling back to the native engine otherwise
+//
+// The input stylesheet is plain CSS, where the @tailwind directives are replaced by:
+// - base: the Tailwind preflight (reset) styles
+// - components: the container class
+// - utilities: the utility classes found within the generated pages and the templates
+use crate::config::Config;
+use crate::error::BuildError;
+use lightningcss::stylesheet::{MinifyOptions, ParserOptions, PrinterOptions, StyleSheet};
+use lightningcss::targets::{Browsers, Targets};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use walkdir::WalkDir;
+
+const PREFLIGHT: &str = include_str!("preflight.css");
+
+// Used when the input stylesheet does not exist
+const DEFAULT_INPUT: &str = "@tailwind base;\n@tailwind components;\n@tailwind utilities;\n";
+
+// Tailwind v3 default color palette (shades 50, 100, 200 ... 900, 950)
+const SHADES: [&str; 11] = [
+    "50", "100", "200", "300", "400", "500", "600", "700", "800", "900", "950",
+];
+const PALETTE: [(&str, [&str; 11]); 22] = [
+    (
+        "slate",
+        [
+            "#f8fafc", "#f1f5f9", "#e2e8f0", "#cbd5e1", "#94a3b8", "#64748b", "#475569", "#334155",
+            "#1e293b", "#0f172a", "#020617",
+        ],
+    ),
+    (
+        "gray",
+        [
+            "#f9fafb", "#f3f4f6", "#e5e7eb", "#d1d5db", "#9ca3af", "#6b7280", "#4b5563", "#374151",
+            "#1f2937", "#111827", "#030712",
+        ],
+    ),
+    (
+        "zinc",
+        [
+            "#fafafa", "#f4f4f5", "#e4e4e7", "#d4d4d8", "#a1a1aa", "#71717a", "#52525b", "#3f3f46",
+            "#27272a", "#18181b", "#09090b",
+        ],
+    ),
+    (
+        "neutral",
+        [
+            "#fafafa", "#f5f5f5", "#e5e5e5", "#d4d4d4", "#a3a3a3", "#737373", "#525252", "#404040",
+            "#262626", "#171717", "#0a0a0a",
+        ],
+    ),
+    (
+        "stone",
+        [
+            "#fafaf9", "#f5f5f4", "#e7e5e4", "#d6d3d1", "#a8a29e", "#78716c", "#57534e", "#44403c",
+            "#292524", "#1c1917", "#0c0a09",
+        ],
+    ),
+    (
+        "red",
+        [
+            "#fef2f2", "#fee2e2", "#fecaca", "#fca5a5", "#f87171", "#ef4444", "#dc2626", "#b91c1c",
+            "#991b1b", "#7f1d1d", "#450a0a",
+        ],
+    ),
+    (
+        "orange",
+        [
+            "#fff7ed", "#ffedd5", "#fed7aa", "#fdba74", "#fb923c", "#f97316", "#ea580c", "#c2410c",
+            "#9a3412", "#7c2d12", "#431407",
+        ],
+    ),
+    (
+        "amber",
+        [
+            "#fffbeb", "#fef3c7", "#fde68a", "#fcd34d", "#fbbf24", "#f59e0b", "#d97706", "#b45309",
+            "#92400e", "#78350f", "#451a03",
+        ],
+    ),
+    (
+        "yellow",
+        [
+            "#fefce8", "#fef9c3", "#fef08a", "#fde047", "#facc15", "#eab308", "#ca8a04", "#a16207",
+            "#854d0e", "#713f12", "#422006",
+        ],
+    ),
+    (
+        "lime",
+        [
+            "#f7fee7", "#ecfccb", "#d9f99d", "#bef264", "#a3e635", "#84cc16", "#65a30d", "#4d7c0f",
+            "#3f6212", "#365314", "#1a2e05",
+        ],
+    ),
+    (
+        "green",
+        [
+            "#f0fdf4", "#dcfce7", "#bbf7d0", "#86efac", "#4ade80", "#22c55e", "#16a34a", "#15803d",
+            "#166534", "#14532d", "#052e16",
+        ],
+    ),
+    (
+        "emerald",
+        [
+            "#ecfdf5", "#d1fae5", "#a7f3d0", "#6ee7b7", "#34d399", "#10b981", "#059669", "#047857",
+            "#065f46", "#064e3b", "#022c22",
+        ],
+    ),
+    (
+        "teal",
+        [
+            "#f0fdfa", "#ccfbf1", "#99f6e4", "#5eead4", "#2dd4bf", "#14b8a6", "#0d9488", "#0f766e",
+            "#115e59", "#134e4a", "#042f2e",
+        ],
+    ),
+    (
+        "cyan",
+        [
+            "#ecfeff", "#cffafe", "#a5f3fc", "#67e8f9", "#22d3ee", "#06b6d4", "#0891b2", "#0e7490",
+            "#155e75", "#164e63", "#083344",
+        ],
+    ),
+    (
+        "sky",
+        [
+            "#f0f9ff", "#e0f2fe", "#bae6fd", "#7dd3fc", "#38bdf8", "#0ea5e9", "#0284c7", "#0369a1",
+            "#075985", "#0c4a6e", "#082f49",
+        ],
+    ),
+    (
+        "blue",
+        [
+            "#eff6ff", "#dbeafe", "#bfdbfe", "#93c5fd", "#60a5fa", "#3b82f6", "#2563eb", "#1d4ed8",
+            "#1e40af", "#1e3a8a", "#172554",
+        ],
+    ),
+    (
+        "indigo",
+        [
+            "#eef2ff", "#e0e7ff", "#c7d2fe", "#a5b4fc", "#818cf8", "#6366f1", "#4f46e5", "#4338ca",
+            "#3730a3", "#312e81", "#1e1b4b",
+        ],
+    ),
+    (
+        "violet",
+        [
+            "#f5f3ff", "#ede9fe", "#ddd6fe", "#c4b5fd", "#a78bfa", "#8b5cf6", "#7c3aed", "#6d28d9",
+            "#5b21b6", "#4c1d95", "#2e1065",
+        ],
+    ),
+    (
+        "purple",
+        [
+            "#faf5ff", "#f3e8ff", "#e9d5ff", "#d8b4fe", "#c084fc", "#a855f7", "#9333ea", "#7e22ce",
+            "#6b21a8", "#581c87", "#3b0764",
+        ],
+    ),
+    (
+        "fuchsia",
+        [
+            "#fdf4ff", "#fae8ff", "#f5d0fe", "#f0abfc", "#e879f9", "#d946ef", "#c026d3", "#a21caf",
+            "#86198f", "#701a75", "#4a044e",
+        ],
+    ),
+    (
+        "pink",
+        [
+            "#fdf2f8", "#fce7f3", "#fbcfe8", "#f9a8d4", "#f472b6", "#ec4899", "#db2777", "#be185d",
+            "#9d174d", "#831843", "#500724",
+        ],
+    ),
+    (
+        "rose",
+        [
+            "#fff1f2", "#ffe4e6", "#fecdd3", "#fda4af", "#fb7185", "#f43f5e", "#e11d48", "#be123c",
+            "#9f1239", "#881337", "#4c0519",
+        ],
+    ),
+];
+const BASE_COLORS: [(&str, &str); 5] = [
+    ("inherit", "inherit"),
+    ("current", "currentColor"),
+    ("transparent", "transparent"),
+    ("black", "#000"),
+    ("white", "#fff"),
+];
+
+const SCREENS: [(&str, &str); 5] = [
+    ("sm", "640px"),
+    ("md", "768px"),
+    ("lg", "1024px"),
+    ("xl", "1280px"),
+    ("2xl", "1536px"),
+];
+
+const FONT_FAMILIES: [(&str, &str); 3] = [
+    ("sans", "ui-sans-serif, system-ui, sans-serif, \"Apple Color Emoji\", \"Segoe UI Emoji\", \"Segoe UI Symbol\", \"Noto Color Emoji\""),
+    ("serif", "ui-serif, Georgia, Cambria, \"Times New Roman\", Times, serif"),
+    ("mono", "ui-monospace, SFMono-Regular, Menlo, Monaco, Consolas, \"Liberation Mono\", \"Courier New\", monospace"),
+];
+
+// Steps of the spacing scale (multiples of 0.25rem)
+const SPACING: [&str; 33] = [
+    "0.5", "1", "1.5", "2", "2.5", "3", "3.5", "4", "5", "6", "7", "8", "9", "10", "11", "12",
+    "14", "16", "20", "24", "28", "32", "36", "40", "44", "48", "52", "56", "60", "64", "72", "80",
+    "96",
+];
+
+// Selector variants, in the order their rules are generated. `&` stands for the class selector
+const SELECTOR_VARIANTS: [(&str, &str); 16] = [
+    ("first", "&:first-child"),
+    ("last", "&:last-child"),
+    ("only", "&:only-child"),
+    ("odd", "&:nth-child(odd)"),
+    ("even", "&:nth-child(even)"),
+    ("visited", "&:visited"),
+    ("target", "&:target"),
+    ("open", "&[open]"),
+    ("focus-within", "&:focus-within"),
+    ("hover", "&:hover"),
+    ("focus", "&:focus"),
+    ("focus-visible", "&:focus-visible"),
+    ("active", "&:active"),
+    ("disabled", "&:disabled"),
+    ("group-hover", ".group:hover &"),
+    ("group-focus", ".group:focus &"),
+];
+
+// Custom properties shared by the backdrop filter utilities
+const BACKDROP_FILTER: &str = "var(--tw-backdrop-blur) var(--tw-backdrop-brightness) var(--tw-backdrop-contrast) var(--tw-backdrop-grayscale) var(--tw-backdrop-hue-rotate) var(--tw-backdrop-invert) var(--tw-backdrop-opacity) var(--tw-backdrop-saturate) var(--tw-backdrop-sepia)";
+
+const SPACE_CHILDREN: &str = " > :not([hidden]) ~ :not([hidden])";
+
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CssEngine {
+    // Generate the utility classes natively
+    #[default]
+    Native,
+    // Run the Tailwind CLI (`npx tailwindcss`) when installed
+    Tailwind,
+    // Do not build a stylesheet
+    None,
+}
+
+// Stylesheet configuration from the configuration YAML file (`css` section)
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CssConfig {
+    pub engine: CssEngine,
+    // Input stylesheet, relative to the template directory
+    pub input: PathBuf,
+    // Generated stylesheet, relative to the output directory
+    pub output: PathBuf,
+    pub minify: bool,
+    // Additions to the default theme (like `theme.extend` within tailwind.config.js)
+    pub theme: Theme,
+}
+
+impl Default for CssConfig {
+    fn default() -> Self {
+        CssConfig {
+            engine: CssEngine::Native,
+            input: PathBuf::from("input.css"),
+            output: PathBuf::from("static/styles/tailwind.css"),
+            minify: true,
+            theme: Theme::default(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Theme {
+    // Either a single color (`brand: "#0f766e"`) or shades (`brand: { 100: "#ccfbf1", ... }`,
+    // where the DEFAULT shade is used for `text-brand`)
+    pub colors: BTreeMap<String, Value>,
+    // Breakpoints for the responsive variants (e.g. `3xl: 1920px`)
+    pub screens: BTreeMap<String, String>,
+    pub font_family: BTreeMap<String, Vec<String>>,
+}
+
+// Generates the CSS of the utility classes from the theme
+pub struct Generator {
+    colors: BTreeMap<String, String>,
+    // sorted by their width
+    screens: Vec<(String, String)>,
+    fonts: BTreeMap<String, String>,
+}
+
+// A utility class matched by one of the plugins
+struct Utility {
+    // position of the utility within its plugin (e.g. `mt` after `m`), as later rules win
+    order: usize,
+    declarations: Vec<(&'static str, String)>,
+    // selector of the elements the declarations apply to, relative to the class
+    children: &'static str,
+}
+
+impl Utility {
+    fn new(order: usize, declarations: Vec<(&'static str, String)>) -> Self {
+        Utility {
+            order,
+            declarations,
+            children: "",
+        }
+    }
+}
+
+type DynamicPlugin = fn(&Generator, &str, bool) -> Option<Utility>;
+
+enum Plugin {
+    // Classes with fixed declarations (e.g. `flex: "display: flex"`)
+    Static(&'static [(&'static str, &'static str)]),
+    // Classes with a value from the theme or an arbitrary value (e.g. `mt-4`, `w-[32rem]`).
+    // Called with the class name (without the `-` of negative values) and whether it is negative
+    Dynamic(DynamicPlugin),
+}
+
+// Plugins in the order of their rules within the stylesheet (the order Tailwind uses)
+const PLUGINS: [Plugin; 44] = [
+    Plugin::Static(&[
+        ("sr-only", "position: absolute; width: 1px; height: 1px; padding: 0; margin: -1px; overflow: hidden; clip: rect(0, 0, 0, 0); white-space: nowrap; border-width: 0"),
+        ("not-sr-only", "position: static; width: auto; height: auto; padding: 0; margin: 0; overflow: visible; clip: auto; white-space: normal"),
+    ]),
+    Plugin::Static(&[
+        ("pointer-events-none", "pointer-events: none"),
+        ("pointer-events-auto", "pointer-events: auto"),
+        ("visible", "visibility: visible"),
+        ("invisible", "visibility: hidden"),
+        ("collapse", "visibility: collapse"),
+    ]),
+    Plugin::Static(&[
+        ("static", "position: static"),
+        ("fixed", "position: fixed"),
+        ("absolute", "position: absolute"),
+        ("relative", "position: relative"),
+        ("sticky", "position: sticky"),
+    ]),
+    Plugin::Dynamic(Generator::inset),
+    Plugin::Dynamic(Generator::z_index),
+    Plugin::Dynamic(Generator::grid_column),
+    Plugin::Dynamic(Generator::margin),
+    Plugin::Static(&[
+        ("box-border", "box-sizing: border-box"),
+        ("box-content", "box-sizing: content-box"),
+    ]),
+    Plugin::Static(&[
+        ("block", "display: block"),
+        ("inline-block", "display: inline-block"),
+        ("inline", "display: inline"),
+        ("flex", "display: flex"),
+        ("inline-flex", "display: inline-flex"),
+        ("table", "display: table"),
+        ("table-row", "display: table-row"),
+        ("table-cell", "display: table-cell"),
+        ("grid", "display: grid"),
+        ("inline-grid", "display: inline-grid"),
+        ("contents", "display: contents"),
+        ("list-item", "display: list-item"),
+        ("hidden", "display: none"),
+    ]),
+    Plugin::Dynamic(Generator::size),
+    Plugin::Static(&[
+        ("flex-1", "flex: 1 1 0%"),
+        ("flex-auto", "flex: 1 1 auto"),
+        ("flex-initial", "flex: 0 1 auto"),
+        ("flex-none", "flex: none"),
+        ("shrink", "flex-shrink: 1"),
+        ("shrink-0", "flex-shrink: 0"),
+        ("grow", "flex-grow: 1"),
+        ("grow-0", "flex-grow: 0"),
+    ]),
+    Plugin::Static(&[
+        ("cursor-auto", "cursor: auto"),
+        ("cursor-default", "cursor: default"),
+        ("cursor-pointer", "cursor: pointer"),
+        ("cursor-wait", "cursor: wait"),
+        ("cursor-text", "cursor: text"),
+        ("cursor-move", "cursor: move"),
+        ("cursor-not-allowed", "cursor: not-allowed"),
+    ]),
+    Plugin::Static(&[
+        ("list-inside", "list-style-position: inside"),
+        ("list-outside", "list-style-position: outside"),
+        ("list-none", "list-style-type: none"),
+        ("list-disc", "list-style-type: disc"),
+        ("list-decimal", "list-style-type: decimal"),
+    ]),
+    Plugin::Dynamic(Generator::grid_template_columns),
+    Plugin::Static(&[
+        ("flex-row", "flex-direction: row"),
+        ("flex-row-reverse", "flex-direction: row-reverse"),
+        ("flex-col", "flex-direction: column"),
+        ("flex-col-reverse", "flex-direction: column-reverse"),
+        ("flex-wrap", "flex-wrap: wrap"),
+        ("flex-wrap-reverse", "flex-wrap: wrap-reverse"),
+        ("flex-nowrap", "flex-wrap: nowrap"),
+    ]),
+    Plugin::Static(&[
+        ("content-center", "align-content: center"),
+        ("content-start", "align-content: flex-start"),
+        ("content-end", "align-content: flex-end"),
+        ("content-between", "align-content: space-between"),
+        ("items-start", "align-items: flex-start"),
+        ("items-end", "align-items: flex-end"),
+        ("items-center", "align-items: center"),
+        ("items-baseline", "align-items: baseline"),
+        ("items-stretch", "align-items: stretch"),
+        ("justify-normal", "justify-content: normal"),
+        ("justify-start", "justify-content: flex-start"),
+        ("justify-end", "justify-content: flex-end"),
+        ("justify-center", "justify-content: center"),
+        ("justify-between", "justify-content: space-between"),
+        ("justify-around", "justify-content: space-around"),
+        ("justify-evenly", "justify-content: space-evenly"),
+    ]),
+    Plugin::Dynamic(Generator::gap),
+    Plugin::Dynamic(Generator::space),
+    Plugin::Static(&[
+        ("self-auto", "align-self: auto"),
+        ("self-start", "align-self: flex-start"),
+        ("self-end", "align-self: flex-end"),
+        ("self-center", "align-self: center"),
+        ("self-stretch", "align-self: stretch"),
+    ]),
+    Plugin::Static(&[
+        ("overflow-auto", "overflow: auto"),
+        ("overflow-hidden", "overflow: hidden"),
+        ("overflow-clip", "overflow: clip"),
+        ("overflow-visible", "overflow: visible"),
+        ("overflow-scroll", "overflow: scroll"),
+        ("overflow-x-auto", "overflow-x: auto"),
+        ("overflow-y-auto", "overflow-y: auto"),
+        ("overflow-x-hidden", "overflow-x: hidden"),
+        ("overflow-y-hidden", "overflow-y: hidden"),
+        ("overflow-x-clip", "overflow-x: clip"),
+        ("overflow-y-clip", "overflow-y: clip"),
+        ("overflow-x-visible", "overflow-x: visible"),
+        ("overflow-y-visible", "overflow-y: visible"),
+        ("overflow-x-scroll", "overflow-x: scroll"),
+        ("overflow-y-scroll", "overflow-y: scroll"),
+    ]),
+    Plugin::Static(&[
+        ("truncate", "overflow: hidden; text-overflow: ellipsis; white-space: nowrap"),
+        ("text-ellipsis", "text-overflow: ellipsis"),
+        ("text-clip", "text-overflow: clip"),
+        ("whitespace-normal", "white-space: normal"),
+        ("whitespace-nowrap", "white-space: nowrap"),
+        ("whitespace-pre", "white-space: pre"),
+        ("whitespace-pre-line", "white-space: pre-line"),
+        ("whitespace-pre-wrap", "white-space: pre-wrap"),
+        ("whitespace-break-spaces", "white-space: break-spaces"),
+        ("break-normal", "overflow-wrap: normal; word-break: normal"),
+        ("break-words", "overflow-wrap: break-word"),
+        ("break-all", "word-break: break-all"),
+    ]),
+    Plugin::Dynamic(Generator::border_radius),
+    Plugin::Dynamic(Generator::border_width),
+    Plugin::Static(&[
+        ("border-solid", "border-style: solid"),
+        ("border-dashed", "border-style: dashed"),
+        ("border-dotted", "border-style: dotted"),
+        ("border-double", "border-style: double"),
+        ("border-hidden", "border-style: hidden"),
+        ("border-none", "border-style: none"),
+    ]),
+    Plugin::Dynamic(Generator::border_color),
+    Plugin::Dynamic(Generator::background_color),
+    Plugin::Static(&[
+        ("object-contain", "object-fit: contain"),
+        ("object-cover", "object-fit: cover"),
+        ("object-fill", "object-fit: fill"),
+        ("object-none", "object-fit: none"),
+        ("object-scale-down", "object-fit: scale-down"),
+    ]),
+    Plugin::Dynamic(Generator::padding),
+    Plugin::Static(&[
+        ("text-left", "text-align: left"),
+        ("text-center", "text-align: center"),
+        ("text-right", "text-align: right"),
+        ("text-justify", "text-align: justify"),
+        ("text-start", "text-align: start"),
+        ("text-end", "text-align: end"),
+    ]),
+    Plugin::Static(&[
+        ("align-baseline", "vertical-align: baseline"),
+        ("align-top", "vertical-align: top"),
+        ("align-middle", "vertical-align: middle"),
+        ("align-bottom", "vertical-align: bottom"),
+        ("align-text-top", "vertical-align: text-top"),
+        ("align-text-bottom", "vertical-align: text-bottom"),
+        ("align-sub", "vertical-align: sub"),
+        ("align-super", "vertical-align: super"),
+    ]),
+    Plugin::Dynamic(Generator::font_family),
+    Plugin::Dynamic(Generator::font_size),
+    Plugin::Static(&[
+        ("font-thin", "font-weight: 100"),
+        ("font-extralight", "font-weight: 200"),
+        ("font-light", "font-weight: 300"),
+        ("font-normal", "font-weight: 400"),
+        ("font-medium", "font-weight: 500"),
+        ("font-semibold", "font-weight: 600"),
+        ("font-bold", "font-weight: 700"),
+        ("font-extrabold", "font-weight: 800"),
+        ("font-black", "font-weight: 900"),
+    ]),
+    Plugin::Static(&[
+        ("uppercase", "text-transform: uppercase"),
+        ("lowercase", "text-transform: lowercase"),
+        ("capitalize", "text-transform: capitalize"),
+        ("normal-case", "text-transform: none"),
+        ("italic", "font-style: italic"),
+        ("not-italic", "font-style: normal"),
+    ]),
+    Plugin::Dynamic(Generator::line_height),
+    Plugin::Dynamic(Generator::letter_spacing),
+    Plugin::Dynamic(Generator::text_color),
+    Plugin::Static(&[
+        ("underline", "text-decoration-line: underline"),
+        ("overline", "text-decoration-line: overline"),
+        ("line-through", "text-decoration-line: line-through"),
+        ("no-underline", "text-decoration-line: none"),
+    ]),
+    Plugin::Dynamic(Generator::underline_offset),
+    Plugin::Static(&[
+        ("antialiased", "-webkit-font-smoothing: antialiased; -moz-osx-font-smoothing: grayscale"),
+        ("subpixel-antialiased", "-webkit-font-smoothing: auto; -moz-osx-font-smoothing: auto"),
+    ]),
+    Plugin::Dynamic(Generator::opacity),
+    Plugin::Dynamic(Generator::box_shadow),
+    Plugin::Dynamic(Generator::backdrop_blur),
+    Plugin::Dynamic(Generator::transition),
+];
+
+// A rule generated for a class, along with its position within the stylesheet
+struct Rule {
+    // media queries (sorted by their rank), selector variants, plugin and position within it
+    key: (Vec<usize>, Vec<usize>, usize, usize),
+    media: Vec<String>,
+    selector: String,
+    declarations: Vec<(&'static str, String)>,
+    important: bool,
+}
+
+impl Generator {
+    pub fn new(theme: &Theme) -> Result<Self, String> {
+        let mut colors = BTreeMap::new();
+        for (name, value) in BASE_COLORS {
+            colors.insert(name.to_string(), value.to_string());
+        }
+        for (name, shades) in PALETTE {
+            for (shade, value) in SHADES.iter().zip(shades) {
+                colors.insert(format!("{}-{}", name, shade), value.to_string());
+            }
+        }
+        for (name, value) in &theme.colors {
+            match value {
+                Value::String(color) => {
+                    colors.insert(name.clone(), color.clone());
+                }
+                Value::Mapping(shades) => {
+                    for (shade, color) in shades {
+                        let shade = match shade {
+                            Value::String(shade) => shade.clone(),
+                            Value::Number(shade) => shade.to_string(),
+                            _ => return Err(format!("invalid shade of color `{}`", name)),
+                        };
+                        let Some(color) = color.as_str() else {
+                            return Err(format!("color `{}-{}` must be a string", name, shade));
+                        };
+                        let key = if shade == "DEFAULT" {
+                            name.clone()
+                        } else {
+                            format!("{}-{}", name, shade)
+                        };
+                        colors.insert(key, color.to_string());
+                    }
+                }
+                _ => {
+                    return Err(format!(
+                        "color `{}` must be a string or a mapping of shades",
+                        name
+                    ))
+                }
+            }
+        }
+
+        let mut screens: BTreeMap<String, String> = SCREENS
+            .iter()
+            .map(|(name, width)| (name.to_string(), width.to_string()))
+            .collect();
+        screens.extend(theme.screens.clone());
+        let mut screens: Vec<(String, String)> = screens.into_iter().collect();
+        for (name, width) in &screens {
+            if screen_width(width).is_none() {
+                return Err(format!("invalid width of screen `{}`: {}", name, width));
+            }
+        }
+        screens.sort_by(|(_, a), (_, b)| {
+            let width = |width: &str| screen_width(width).unwrap_or_default();
+            width(a).total_cmp(&width(b))
+        });
+
+        let mut fonts: BTreeMap<String, String> = FONT_FAMILIES
+            .iter()
+            .map(|(name, family)| (name.to_string(), family.to_string()))
+            .collect();
+        for (name, families) in &theme.font_family {
+            let families: Vec<String> = families
+                .iter()
+                .map(|family| {
+                    if family.contains(' ') && !family.starts_with('"') {
+                        format!("\"{}\"", family)
+                    } else {
+                        family.clone()
+                    }
+                })
+                .collect();
+            fonts.insert(name.clone(), families.join(", "));
+        }
+
+        Ok(Generator {
+            colors,
+            screens,
+            fonts,
+        })
+    }
+
+    // CSS of the components (the container) and of the utilities used by the candidates.
+    // Candidates that are not utility classes are ignored
+    pub fn generate(&self, candidates: &BTreeSet<String>) -> (String, String) {
+        let mut components = String::new();
+        if candidates.contains("container") {
+            components.push_str(".container {\n  width: 100%;\n}\n");
+            for (_, width) in &self.screens {
+                writeln!(
+                    components,
+                    "@media (min-width: {}) {{\n  .container {{\n    max-width: {};\n  }}\n}}",
+                    width, width
+                )
+                .unwrap();
+            }
+        }
+
+        let mut rules: Vec<Rule> = candidates
+            .iter()
+            .filter_map(|candidate| self.rule(candidate))
+            .collect();
+        rules.sort_by(|a, b| (&a.key, &a.selector).cmp(&(&b.key, &b.selector)));
+        debug!("Generated {} utility class(es)", rules.len());
+
+        let mut utilities = String::new();
+        let mut media: &[String] = &[];
+        for rule in &rules {
+            if rule.media != media {
+                for _ in media {
+                    utilities.push_str("}\n");
+                }
+                for condition in &rule.media {
+                    writeln!(utilities, "@media {} {{", condition).unwrap();
+                }
+                media = &rule.media;
+            }
+            writeln!(utilities, "{} {{", rule.selector).unwrap();
+            for (property, value) in &rule.declarations {
+                let important = if rule.important { " !important" } else { "" };
+                writeln!(utilities, "  {}: {}{};", property, value, important).unwrap();
+            }
+            utilities.push_str("}\n");
+        }
+        for _ in media {
+            utilities.push_str("}\n");
+        }
+
+        (components, utilities)
+    }
+
+    // Rule of a class such as `sm:hover:-mt-4`: variants separated by colons, an optional `!`
+    // (important) and `-` (negative value), then the utility itself
+    fn rule(&self, class: &str) -> Option<Rule> {
+        let mut parts = split_outside_brackets(class, ':');
+        let utility = parts.pop()?;
+        let (important, utility) = match utility.strip_prefix('!') {
+            Some(utility) => (true, utility),
+            None => (false, utility),
+        };
+        let (negative, utility) = match utility.strip_prefix('-') {
+            Some(utility) => (true, utility),
+            None => (false, utility),
+        };
+        if utility.is_empty() {
+            return None;
+        }
+
+        let (rank, utility) =
+            PLUGINS
+                .iter()
+                .enumerate()
+                .find_map(|(rank, plugin)| match plugin {
+                    Plugin::Static(classes) if !negative => classes
+                        .iter()
+                        .position(|(name, _)| *name == utility)
+                        .map(|order| {
+                            let declarations = classes[order]
+                                .1
+                                .split("; ")
+                                .filter_map(|declaration| declaration.split_once(": "))
+                                .map(|(property, value)| (property, value.to_string()))
+                                .collect();
+                            (rank, Utility::new(order, declarations))
+                        }),
+                    Plugin::Static(_) => None,
+                    Plugin::Dynamic(plugin) => plugin(self, utility, negative).map(|u| (rank, u)),
+                })?;
+
+        let mut selector = format!(".{}", escape_class(class));
+        let mut media = Vec::new();
+        let mut variant_ranks = Vec::new();
+        for variant in parts {
+            if let Some(rank) = self.screens.iter().position(|(name, _)| name == variant) {
+                media.push((rank, format!("(min-width: {})", self.screens[rank].1)));
+            } else if variant == "dark" {
+                media.push((
+                    self.screens.len(),
+                    "(prefers-color-scheme: dark)".to_string(),
+                ));
+            } else if variant == "print" {
+                media.push((self.screens.len() + 1, "print".to_string()));
+            } else if let Some(rank) = SELECTOR_VARIANTS
+                .iter()
+                .position(|(name, _)| *name == variant)
+            {
+                selector = SELECTOR_VARIANTS[rank].1.replace('&', &selector);
+                variant_ranks.push(rank);
+            } else if let Some(group) = variant.strip_prefix("group-").and_then(arbitrary) {
+                // e.g. group-[.menu-open]: applies when the group element has the class
+                selector = format!(".group{} {}", group.replace(' ', ""), selector);
+                variant_ranks.push(SELECTOR_VARIANTS.len());
+            } else {
+                return None;
+            }
+        }
+        media.sort();
+        selector.push_str(utility.children);
+
+        Some(Rule {
+            key: (
+                media.iter().map(|(rank, _)| *rank).collect(),
+                variant_ranks,
+                rank,
+                utility.order,
+            ),
+            media: media.into_iter().map(|(_, condition)| condition).collect(),
+            selector,
+            declarations: utility.declarations,
+            important,
+        })
+    }
+
+    fn inset(&self, name: &str, negative: bool) -> Option<Utility> {
+        const SIDES: [(&str, &[&str]); 9] = [
+            ("inset", &["inset"]),
+            ("inset-x", &["left", "right"]),
+            ("inset-y", &["top", "bottom"]),
+            ("start", &["inset-inline-start"]),
+            ("end", &["inset-inline-end"]),
+            ("top", &["top"]),
+            ("right", &["right"]),
+            ("bottom", &["bottom"]),
+            ("left", &["left"]),
+        ];
+        let (order, value) = split_prefix(name, SIDES.map(|(prefix, _)| prefix))?;
+        let value = match value {
+            "auto" if !negative => "auto".to_string(),
+            "full" => signed("100%".to_string(), negative),
+            _ => signed(spacing(value).or_else(|| fraction(value))?, negative),
+        };
+        Some(Utility::new(order, declare(SIDES[order].1, &value)))
+    }
+
+    fn z_index(&self, name: &str, negative: bool) -> Option<Utility> {
+        let value = name.strip_prefix("z-")?;
+        let value = match value {
+            "auto" if !negative => "auto".to_string(),
+            "0" | "10" | "20" | "30" | "40" | "50" => signed(value.to_string(), negative),
+            _ => signed(arbitrary(value)?, negative),
+        };
+        Some(Utility::new(0, vec![("z-index", value)]))
+    }
+
+    fn grid_column(&self, name: &str, negative: bool) -> Option<Utility> {
+        let value = match name.strip_prefix("col-span-")? {
+            "full" => "1 / -1".to_string(),
+            span => format!("span {} / span {}", grid_count(span)?, span),
+        };
+        (!negative).then(|| Utility::new(0, vec![("grid-column", value)]))
+    }
+
+    fn margin(&self, name: &str, negative: bool) -> Option<Utility> {
+        const SIDES: [(&str, &[&str]); 9] = [
+            ("m", &["margin"]),
+            ("mx", &["margin-left", "margin-right"]),
+            ("my", &["margin-top", "margin-bottom"]),
+            ("ms", &["margin-inline-start"]),
+            ("me", &["margin-inline-end"]),
+            ("mt", &["margin-top"]),
+            ("mr", &["margin-right"]),
+            ("mb", &["margin-bottom"]),
+            ("ml", &["margin-left"]),
+        ];
+        let (order, value) = split_prefix(name, SIDES.map(|(prefix, _)| prefix))?;
+        let value = match value {
+            "auto" if !negative => "auto".to_string(),
+            _ => signed(spacing(value)?, negative),
+        };
+        Some(Utility::new(order, declare(SIDES[order].1, &value)))
+    }
+
+    // Width & height (size sets both)
+    fn size(&self, name: &str, negative: bool) -> Option<Utility> {
+        const PROPERTIES: [(&str, &[&str]); 7] = [
+            ("size", &["width", "height"]),
+            ("h", &["height"]),
+            ("max-h", &["max-height"]),
+            ("min-h", &["min-height"]),
+            ("w", &["width"]),
+            ("min-w", &["min-width"]),
+            ("max-w", &["max-width"]),
+        ];
+        if negative {
+            return None;
+        }
+        let (order, value) = split_prefix(name, PROPERTIES.map(|(prefix, _)| prefix))?;
+        let prefix = PROPERTIES[order].0;
+        let viewport = if prefix.ends_with('h') { "vh" } else { "vw" };
+        let value = match value {
+            "auto" if !prefix.contains('-') => "auto".to_string(),
+            "none" if prefix.starts_with("max-") => "none".to_string(),
+            "full" => "100%".to_string(),
+            "screen" if prefix != "size" => format!("100{}", viewport),
+            "svh" | "lvh" | "dvh" if viewport == "vh" => format!("100{}", value),
+            "svw" | "lvw" | "dvw" if viewport == "vw" => format!("100{}", value),
+            "min" => "min-content".to_string(),
+            "max" => "max-content".to_string(),
+            "fit" => "fit-content".to_string(),
+            "prose" if prefix == "max-w" => "65ch".to_string(),
+            _ if prefix == "max-w" => match max_width(value) {
+                Some(width) => width.to_string(),
+                None => match value.strip_prefix("screen-") {
+                    Some(screen) => self
+                        .screens
+                        .iter()
+                        .find(|(name, _)| name == screen)
+                        .map(|(_, width)| width.clone())?,
+                    None => spacing(value)?,
+                },
+            },
+            _ => spacing(value).or_else(|| fraction(value))?,
+        };
+        Some(Utility::new(order, declare(PROPERTIES[order].1, &value)))
+    }
+
+    fn grid_template_columns(&self, name: &str, negative: bool) -> Option<Utility> {
+        let value = match name.strip_prefix("grid-cols-")? {
+            "none" => "none".to_string(),
+            count => match grid_count(count) {
+                Some(count) => format!("repeat({}, minmax(0, 1fr))", count),
+                None => arbitrary(count)?,
+            },
+        };
+        (!negative).then(|| Utility::new(0, vec![("grid-template-columns", value)]))
+    }
+
+    fn gap(&self, name: &str, negative: bool) -> Option<Utility> {
+        const PROPERTIES: [(&str, &str); 3] = [
+            ("gap", "gap"),
+            ("gap-x", "column-gap"),
+            ("gap-y", "row-gap"),
+        ];
+        let (order, value) = split_prefix(name, PROPERTIES.map(|(prefix, _)| prefix))?;
+        let value = spacing(value).filter(|_| !negative)?;
+        Some(Utility::new(order, vec![(PROPERTIES[order].1, value)]))
+    }
+
+    // Space between the children of an element (margins on every child but the first)
+    fn space(&self, name: &str, negative: bool) -> Option<Utility> {
+        let (order, value) = split_prefix(name, ["space-x", "space-y"])?;
+        let value = signed(spacing(value)?, negative);
+        let declarations = if order == 0 {
+            vec![
+                ("--tw-space-x-reverse", "0".to_string()),
+                (
+                    "margin-right",
+                    format!("calc({} * var(--tw-space-x-reverse))", value),
+                ),
+                (
+                    "margin-left",
+                    format!("calc({} * calc(1 - var(--tw-space-x-reverse)))", value),
+                ),
+            ]
+        } else {
+            vec![
+                ("--tw-space-y-reverse", "0".to_string()),
+                (
+                    "margin-top",
+                    format!("calc({} * calc(1 - var(--tw-space-y-reverse)))", value),
+                ),
+                (
+                    "margin-bottom",
+                    format!("calc({} * var(--tw-space-y-reverse))", value),
+                ),
+            ]
+        };
+        Some(Utility {
+            order,
+            declarations,
+            children: SPACE_CHILDREN,
+        })
+    }
+
+    fn border_radius(&self, name: &str, negative: bool) -> Option<Utility> {
+        const CORNERS: [(&str, &[&str]); 9] = [
+            ("rounded", &["border-radius"]),
+            (
+                "rounded-t",
+                &["border-top-left-radius", "border-top-right-radius"],
+            ),
+            (
+                "rounded-r",
+                &["border-top-right-radius", "border-bottom-right-radius"],
+            ),
+            (
+                "rounded-b",
+                &["border-bottom-right-radius", "border-bottom-left-radius"],
+            ),
+            (
+                "rounded-l",
+                &["border-top-left-radius", "border-bottom-left-radius"],
+            ),
+            ("rounded-tl", &["border-top-left-radius"]),
+            ("rounded-tr", &["border-top-right-radius"]),
+            ("rounded-br", &["border-bottom-right-radius"]),
+            ("rounded-bl", &["border-bottom-left-radius"]),
+        ];
+        let (order, value) = split_prefix(name, CORNERS.map(|(prefix, _)| prefix))?;
+        let value = match value {
+            "none" => "0px",
+            "sm" => "0.125rem",
+            "" => "0.25rem",
+            "md" => "0.375rem",
+            "lg" => "0.5rem",
+            "xl" => "0.75rem",
+            "2xl" => "1rem",
+            "3xl" => "1.5rem",
+            "full" => "9999px",
+            _ => return None,
+        };
+        (!negative).then(|| Utility::new(order, declare(CORNERS[order].1, value)))
+    }
+
+    fn border_width(&self, name: &str, negative: bool) -> Option<Utility> {
+        const SIDES: [(&str, &[&str]); 7] = [
+            ("border", &["border-width"]),
+            ("border-x", &["border-left-width", "border-right-width"]),
+            ("border-y", &["border-top-width", "border-bottom-width"]),
+            ("border-t", &["border-top-width"]),
+            ("border-r", &["border-right-width"]),
+            ("border-b", &["border-bottom-width"]),
+            ("border-l", &["border-left-width"]),
+        ];
+        let (order, value) = split_prefix(name, SIDES.map(|(prefix, _)| prefix))?;
+        let value = match value {
+            "" => "1px".to_string(),
+            "0" | "2" | "4" | "8" => format!("{}px", value),
+            _ => arbitrary(value).filter(|value| is_length(value))?,
+        };
+        (!negative).then(|| Utility::new(order, declare(SIDES[order].1, &value)))
+    }
+
+    fn border_color(&self, name: &str, negative: bool) -> Option<Utility> {
+        let value = name.strip_prefix("border-").filter(|_| !negative)?;
+        let declarations = self.color(value, "border-color", "--tw-border-opacity")?;
+        Some(Utility::new(0, declarations))
+    }
+
+    fn background_color(&self, name: &str, negative: bool) -> Option<Utility> {
+        let value = name.strip_prefix("bg-").filter(|_| !negative)?;
+        let declarations = self.color(value, "background-color", "--tw-bg-opacity")?;
+        Some(Utility::new(0, declarations))
+    }
+
+    fn padding(&self, name: &str, negative: bool) -> Option<Utility> {
+        const SIDES: [(&str, &[&str]); 9] = [
+            ("p", &["padding"]),
+            ("px", &["padding-left", "padding-right"]),
+            ("py", &["padding-top", "padding-bottom"]),
+            ("ps", &["padding-inline-start"]),
+            ("pe", &["padding-inline-end"]),
+            ("pt", &["padding-top"]),
+            ("pr", &["padding-right"]),
+            ("pb", &["padding-bottom"]),
+            ("pl", &["padding-left"]),
+        ];
+        let (order, value) = split_prefix(name, SIDES.map(|(prefix, _)| prefix))?;
+        let value = spacing(value).filter(|_| !negative)?;
+        Some(Utility::new(order, declare(SIDES[order].1, &value)))
+    }
+
+    fn font_family(&self, name: &str, negative: bool) -> Option<Utility> {
+        let value = name.strip_prefix("font-").filter(|_| !negative)?;
+        let family = match self.fonts.get(value) {
+            Some(family) => family.clone(),
+            None => arbitrary(value)?,
+        };
+        Some(Utility::new(0, vec![("font-family", family)]))
+    }
+
+    fn font_size(&self, name: &str, negative: bool) -> Option<Utility> {
+        let value = name.strip_prefix("text-").filter(|_| !negative)?;
+        let (size, line_height) = match value {
+            "xs" => ("0.75rem", "1rem"),
+            "sm" => ("0.875rem", "1.25rem"),
+            "base" => ("1rem", "1.5rem"),
+            "lg" => ("1.125rem", "1.75rem"),
+            "xl" => ("1.25rem", "1.75rem"),
+            "2xl" => ("1.5rem", "2rem"),
+            "3xl" => ("1.875rem", "2.25rem"),
+            "4xl" => ("2.25rem", "2.5rem"),
+            "5xl" => ("3rem", "1"),
+            "6xl" => ("3.75rem", "1"),
+            "7xl" => ("4.5rem", "1"),
+            "8xl" => ("6rem", "1"),
+            "9xl" => ("8rem", "1"),
+            _ => {
+                let size = arbitrary(value).filter(|value| is_length(value))?;
+                return Some(Utility::new(0, vec![("font-size", size)]));
+            }
+        };
+        Some(Utility::new(
+            0,
+            vec![
+                ("font-size", size.to_string()),
+                ("line-height", line_height.to_string()),
+            ],
+        ))
+    }
+
+    fn line_height(&self, name: &str, negative: bool) -> Option<Utility> {
+        let value = name.strip_prefix("leading-").filter(|_| !negative)?;
+        let value = match value {
+            "none" => "1".to_string(),
+            "tight" => "1.25".to_string(),
+            "snug" => "1.375".to_string(),
+            "normal" => "1.5".to_string(),
+            "relaxed" => "1.625".to_string(),
+            "loose" => "2".to_string(),
+            "3" | "4" | "5" | "6" | "7" | "8" | "9" | "10" => spacing(value)?,
+            _ => arbitrary(value)?,
+        };
+        Some(Utility::new(0, vec![("line-height", value)]))
+    }
+
+    fn letter_spacing(&self, name: &str, negative: bool) -> Option<Utility> {
+        let value = match name.strip_prefix("tracking-")? {
+            "tighter" => "-0.05em".to_string(),
+            "tight" => "-0.025em".to_string(),
+            "normal" => "0em".to_string(),
+            "wide" => "0.025em".to_string(),
+            "wider" => "0.05em".to_string(),
+            "widest" => "0.1em".to_string(),
+            value => arbitrary(value)?,
+        };
+        Some(Utility::new(
+            0,
+            vec![("letter-spacing", signed(value, negative))],
+        ))
+    }
+
+    fn text_color(&self, name: &str, negative: bool) -> Option<Utility> {
+        let value = name.strip_prefix("text-").filter(|_| !negative)?;
+        let declarations = self.color(value, "color", "--tw-text-opacity")?;
+        Some(Utility::new(0, declarations))
+    }
+
+    fn underline_offset(&self, name: &str, negative: bool) -> Option<Utility> {
+        let value = match name.strip_prefix("underline-offset-")? {
+            "auto" => "auto".to_string(),
+            value @ ("0" | "1" | "2" | "4" | "8") => format!("{}px", value),
+            value => arbitrary(value)?,
+        };
+        Some(Utility::new(
+            0,
+            vec![("text-underline-offset", signed(value, negative))],
+        ))
+    }
+
+    fn opacity(&self, name: &str, negative: bool) -> Option<Utility> {
+        let value = name.strip_prefix("opacity-").filter(|_| !negative)?;
+        Some(Utility::new(0, vec![("opacity", opacity(value)?)]))
+    }
+
+    fn box_shadow(&self, name: &str, negative: bool) -> Option<Utility> {
+        let shadow = match name {
+            "shadow-sm" => "0 1px 2px 0 rgb(0 0 0 / 0.05)",
+            "shadow" => "0 1px 3px 0 rgb(0 0 0 / 0.1), 0 1px 2px -1px rgb(0 0 0 / 0.1)",
+            "shadow-md" => "0 4px 6px -1px rgb(0 0 0 / 0.1), 0 2px 4px -2px rgb(0 0 0 / 0.1)",
+            "shadow-lg" => "0 10px 15px -3px rgb(0 0 0 / 0.1), 0 4px 6px -4px rgb(0 0 0 / 0.1)",
+            "shadow-xl" => "0 20px 25px -5px rgb(0 0 0 / 0.1), 0 8px 10px -6px rgb(0 0 0 / 0.1)",
+            "shadow-2xl" => "0 25px 50px -12px rgb(0 0 0 / 0.25)",
+            "shadow-inner" => "inset 0 2px 4px 0 rgb(0 0 0 / 0.05)",
+            "shadow-none" => "0 0 #0000",
+            _ => return None,
+        };
+        // Same shadow with its color replaced by --tw-shadow-color (for colored shadows)
+        let colored = shadow
+            .split(", ")
+            .map(|shadow| match shadow.find("rgb(") {
+                Some(index) => format!("{}var(--tw-shadow-color)", &shadow[..index]),
+                None => shadow.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        (!negative).then(|| {
+            Utility::new(
+                0,
+                vec![
+                    ("--tw-shadow", shadow.to_string()),
+                    ("--tw-shadow-colored", colored),
+                    (
+                        "box-shadow",
+                        "var(--tw-ring-offset-shadow, 0 0 #0000), var(--tw-ring-shadow, 0 0 #0000), var(--tw-shadow)".to_string(),
+                    ),
+                ],
+            )
+        })
+    }
+
+    fn backdrop_blur(&self, name: &str, negative: bool) -> Option<Utility> {
+        let (_, value) = split_prefix(name, ["backdrop-blur"])?;
+        let blur = match value {
+            "none" => "",
+            "sm" => "blur(4px)",
+            "" => "blur(8px)",
+            "md" => "blur(12px)",
+            "lg" => "blur(16px)",
+            "xl" => "blur(24px)",
+            "2xl" => "blur(40px)",
+            "3xl" => "blur(64px)",
+            _ => return None,
+        };
+        (!negative).then(|| {
+            Utility::new(
+                0,
+                vec![
+                    ("--tw-backdrop-blur", blur.to_string()),
+                    ("-webkit-backdrop-filter", BACKDROP_FILTER.to_string()),
+                    ("backdrop-filter", BACKDROP_FILTER.to_string()),
+                ],
+            )
+        })
+    }
+
+    fn transition(&self, name: &str, negative: bool) -> Option<Utility> {
+        const COLORS: &str =
+            "color, background-color, border-color, text-decoration-color, fill, stroke";
+        if negative {
+            return None;
+        }
+        let (order, value) = split_prefix(name, ["transition", "duration", "ease"])?;
+        let declarations = match (order, value) {
+            (0, "none") => vec![("transition-property", "none".to_string())],
+            (0, _) => {
+                let property = match value {
+                    "" => format!(
+                        "{}, opacity, box-shadow, transform, filter, backdrop-filter",
+                        COLORS
+                    ),
+                    "all" => "all".to_string(),
+                    "colors" => COLORS.to_string(),
+                    "opacity" => "opacity".to_string(),
+                    "shadow" => "box-shadow".to_string(),
+                    "transform" => "transform".to_string(),
+                    _ => return None,
+                };
+                vec![
+                    ("transition-property", property),
+                    (
+                        "transition-timing-function",
+                        "cubic-bezier(0.4, 0, 0.2, 1)".to_string(),
+                    ),
+                    ("transition-duration", "150ms".to_string()),
+                ]
+            }
+            (1, "0" | "75" | "100" | "150" | "200" | "300" | "500" | "700" | "1000") => {
+                vec![("transition-duration", format!("{}ms", value))]
+            }
+            (2, _) => {
+                let timing = match value {
+                    "linear" => "linear",
+                    "in" => "cubic-bezier(0.4, 0, 1, 1)",
+                    "out" => "cubic-bezier(0, 0, 0.2, 1)",
+                    "in-out" => "cubic-bezier(0.4, 0, 0.2, 1)",
+                    _ => return None,
+                };
+                vec![("transition-timing-function", timing.to_string())]
+            }
+            _ => return None,
+        };
+        Some(Utility::new(order, declarations))
+    }
+
+    // Declarations of a color utility. Colors are a theme color or an arbitrary color, with an
+    // optional opacity (`bg-black/50`, `bg-white/[.85]`). The opacity of hex colors can be
+    // changed through the opacity custom property (as with Tailwind)
+    fn color(
+        &self,
+        value: &str,
+        property: &'static str,
+        opacity_property: &'static str,
+    ) -> Option<Vec<(&'static str, String)>> {
+        let mut parts = split_outside_brackets(value, '/');
+        let alpha = match parts.len() {
+            1 => None,
+            2 => Some(opacity(parts.pop()?)?),
+            _ => return None,
+        };
+        let name = parts.pop()?;
+        let color = match self.colors.get(name) {
+            Some(color) => color.clone(),
+            None => arbitrary(name).filter(|value| is_color(value))?,
+        };
+
+        match (hex_to_rgb(&color), alpha) {
+            (Some([r, g, b]), None) => Some(vec![
+                (opacity_property, "1".to_string()),
+                (
+                    property,
+                    format!("rgb({} {} {} / var({}, 1))", r, g, b, opacity_property),
+                ),
+            ]),
+            (Some([r, g, b]), Some(alpha)) => Some(vec![(
+                property,
+                format!("rgb({} {} {} / {})", r, g, b, alpha),
+            )]),
+            (None, None) => Some(vec![(property, color)]),
+            // the opacity of other colors (e.g. currentColor) cannot be changed
+            (None, Some(_)) => None,
+        }
+    }
+}
+
+// Match a class name against prefixes (e.g. `mx-4` against `m` & `mx`), returning the index of
+// the longest matching prefix and the value after it (empty for the class without a value,
+// such as `rounded`)
+fn split_prefix<'a, const N: usize>(
+    name: &'a str,
+    prefixes: [&str; N],
+) -> Option<(usize, &'a str)> {
+    prefixes
+        .iter()
+        .enumerate()
+        .filter_map(|(index, prefix)| {
+            let rest = name.strip_prefix(prefix)?;
+            if rest.is_empty() {
+                Some((index, prefix.len(), rest))
+            } else {
+                rest.strip_prefix('-')
+                    .filter(|value| !value.is_empty())
+                    .map(|value| (index, prefix.len(), value))
+            }
+        })
+        .max_by_key(|(_, length, _)| *length)
+        .map(|(index, _, value)| (index, value))
+}
+
+// Split at the separators which are not within an arbitrary value (e.g. `group-[.a:hover]:flex`)
+fn split_outside_brackets(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (index, c) in text.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            c if c == separator && depth == 0 => {
+                parts.push(&text[start..index]);
+                start = index + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+// Value within square brackets, where underscores stand for spaces (e.g. `w-[calc(100%_-_2rem)]`).
+// Values which could escape their declaration are rejected
+fn arbitrary(value: &str) -> Option<String> {
+    let value = value.strip_prefix('[')?.strip_suffix(']')?;
+    if value.is_empty() || value.contains([';', '{', '}', '[', ']']) {
+        return None;
+    }
+    Some(value.replace('_', " "))
+}
+
+// Spacing scale, `px` or an arbitrary value
+fn spacing(value: &str) -> Option<String> {
+    match value {
+        "0" => Some("0px".to_string()),
+        "px" => Some("1px".to_string()),
+        _ if SPACING.contains(&value) => Some(format!("{}rem", value.parse::<f64>().ok()? / 4.0)),
+        _ => arbitrary(value),
+    }
+}
+
+// Fractions such as 1/2 or 2/3, as percentages
+fn fraction(value: &str) -> Option<String> {
+    let (numerator, denominator) = value.split_once('/')?;
+    let numerator: u32 = numerator.parse().ok()?;
+    let denominator: u32 = denominator.parse().ok()?;
+    if numerator == 0 || numerator >= denominator || ![2, 3, 4, 5, 6, 12].contains(&denominator) {
+        return None;
+    }
+    Some(format!(
+        "{}%",
+        number(100.0 * numerator as f64 / denominator as f64)
+    ))
+}
+
+// Opacity from 0 to 100 in steps of 5, or an arbitrary value
+fn opacity(value: &str) -> Option<String> {
+    match value.parse::<u32>() {
+        Ok(percent) if percent <= 100 && percent % 5 == 0 => Some(number(percent as f64 / 100.0)),
+        Ok(_) => None,
+        Err(_) => arbitrary(value),
+    }
+}
+
+fn max_width(value: &str) -> Option<&'static str> {
+    Some(match value {
+        "0" => "0rem",
+        "xs" => "20rem",
+        "sm" => "24rem",
+        "md" => "28rem",
+        "lg" => "32rem",
+        "xl" => "36rem",
+        "2xl" => "42rem",
+        "3xl" => "48rem",
+        "4xl" => "56rem",
+        "5xl" => "64rem",
+        "6xl" => "72rem",
+        "7xl" => "80rem",
+        _ => return None,
+    })
+}
+
+fn grid_count(value: &str) -> Option<u32> {
+    value.parse().ok().filter(|count| (1..=12).contains(count))
+}
+
+fn declare(properties: &[&'static str], value: &str) -> Vec<(&'static str, String)> {
+    properties
+        .iter()
+        .map(|property| (*property, value.to_string()))
+        .collect()
+}
+
+fn signed(value: String, negative: bool) -> String {
+    if !negative || value == "0px" {
+        value
+    } else if value.starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+        format!("-{}", value)
+    } else {
+        format!("calc({} * -1)", value)
+    }
+}
+
+// Number with at most 6 decimals (e.g. 33.333333)
+fn number(value: f64) -> String {
+    let number = format!("{:.6}", value);
+    number
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+fn is_length(value: &str) -> bool {
+    value.starts_with(|c: char| c.is_ascii_digit() || c == '.') || value.starts_with("calc(")
+}
+
+fn is_color(value: &str) -> bool {
+    value.starts_with('#') || ["rgb", "hsl"].iter().any(|f| value.starts_with(f))
+}
+
+fn hex_to_rgb(color: &str) -> Option<[u8; 3]> {
+    let hex = color.strip_prefix('#')?;
+    let digits: Vec<u8> = hex
+        .chars()
+        .map(|c| c.to_digit(16).map(|digit| digit as u8))
+        .collect::<Option<_>>()?;
+    match digits.as_slice() {
+        [r, g, b] => Some([r * 17, g * 17, b * 17]),
+        [r1, r2, g1, g2, b1, b2] => Some([r1 * 16 + r2, g1 * 16 + g2, b1 * 16 + b2]),
+        _ => None,
+    }
+}
+
+// Width of a screen in pixels (for ordering the responsive variants)
+fn screen_width(width: &str) -> Option<f64> {
+    let (number, unit) = width.split_at(width.find(|c: char| c.is_ascii_alphabetic())?);
+    let number: f64 = number
+        .parse()
+        .ok()
+        .filter(|number: &f64| number.is_finite())?;
+    match unit {
+        "px" => Some(number),
+        "em" | "rem" => Some(number * 16.0),
+        _ => None,
+    }
+}
+
+// Escape the characters of a class name that have a meaning within selectors (e.g. `sm:flex`
+// becomes `sm\:flex`)
+fn escape_class(class: &str) -> String {
+    let mut escaped = String::new();
+    for (index, c) in class.chars().enumerate() {
+        if index == 0 && c.is_ascii_digit() {
+            write!(escaped, "\\{:x} ", c as u32).unwrap();
+        } else if c.is_ascii_alphanumeric() || c == '-' || c == '_' || !c.is_ascii() {
+            escaped.push(c);
+        } else {
+            escaped.push('\\');
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+// Candidate class names: the class attributes of the generated pages, and every token of the
+// templates (classes can also be added by scripts, or be part of template expressions)
+fn collect_candidates(
+    output_dir: &Path,
+    template_dir: &Path,
+) -> Result<BTreeSet<String>, BuildError> {
+    let mut candidates = BTreeSet::new();
+    let files = |dir: &Path, extensions: &'static [&'static str]| {
+        WalkDir::new(dir)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(move |entry| {
+                entry.file_type().is_file()
+                    && entry
+                        .path()
+                        .extension()
+                        .and_then(|extension| extension.to_str())
+                        .is_some_and(|extension| extensions.contains(&extension))
+            })
+    };
+
+    for entry in files(output_dir, &["html"]) {
+        let html = fs::read_to_string(entry.path()).map_err(|e| BuildError::io(entry.path(), e))?;
+        for classes in class_attributes(&html) {
+            candidates.extend(classes.split_whitespace().map(str::to_string));
+        }
+    }
+    for entry in files(template_dir, &["html", "js"]) {
+        let template =
+            fs::read_to_string(entry.path()).map_err(|e| BuildError::io(entry.path(), e))?;
+        candidates.extend(
+            template
+                .split(|c: char| c.is_whitespace() || "\"'`<>={};".contains(c))
+                .filter(|token| !token.is_empty())
+                .map(str::to_string),
+        );
+    }
+    Ok(candidates)
+}
+
+// Values of the class attributes within the HTML (quoted or not, as minified pages leave out
+// unnecessary quotes)
+fn class_attributes(html: &str) -> Vec<&str> {
+    let mut values = Vec::new();
+    let mut rest = html;
+    while let Some(index) = rest.find("class=") {
+        let preceded_by_space = rest[..index].ends_with(|c: char| c.is_whitespace());
+        rest = &rest[index + "class=".len()..];
+        if !preceded_by_space {
+            continue;
+        }
+        let (value, remaining) = match rest.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let value = &rest[1..];
+                let end = value.find(quote).unwrap_or(value.len());
+                (&value[..end], &value[end..])
+            }
+            _ => {
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || c == '>')
+                    .unwrap_or(rest.len());
+                (&rest[..end], &rest[end..])
+            }
+        };
+        values.push(value);
+        rest = remaining;
+    }
+    values
+}
+
+// Replace the @tailwind directives of the input stylesheet with the generated CSS
+fn expand_directives(input: &str, components: &str, utilities: &str) -> String {
+    input
+        .replace("@tailwind base;", PREFLIGHT)
+        .replace("@tailwind components;", components)
+        .replace("@tailwind utilities;", utilities)
+        .replace("@tailwind variants;", "")
+}
+
+// Browsers the minified CSS must support (the syntax is not modernized past them, e.g. media
+// query ranges, and vendor prefixes they need are kept). Versions are encoded as major << 16
+fn targets() -> Targets {
+    Targets::from(Browsers {
+        chrome: Some(90 << 16),
+        edge: Some(90 << 16),
+        firefox: Some(88 << 16),
+        safari: Some(14 << 16),
+        ios_saf: Some(14 << 16),
+        ..Browsers::default()
+    })
+}
+
+// Minify a stylesheet (the generated one, or a stylesheet copied from the static folder)
+pub fn minify_css(css: &str, path: &Path) -> Result<String, String> {
+    let options = ParserOptions {
+        filename: path.display().to_string(),
+        ..ParserOptions::default()
+    };
+    let mut stylesheet = StyleSheet::parse(css, options).map_err(|e| e.to_string())?;
+    stylesheet
+        .minify(MinifyOptions {
+            targets: targets(),
+            ..MinifyOptions::default()
+        })
+        .map_err(|e| e.to_string())?;
+    let printed = stylesheet
+        .to_css(PrinterOptions {
+            minify: true,
+            targets: targets(),
+            ..PrinterOptions::default()
+        })
+        .map_err(|e| e.to_string())?;
+    Ok(printed.code)
+}
+
+// Path of the generated stylesheet within the static folder of the build (for the asset
+// manifest), if there is one
+pub fn stylesheet_asset_path(config: &CssConfig) -> Option<&Path> {
+    if config.engine == CssEngine::None {
+        return None;
+    }
+    config.output.strip_prefix("static").ok()
+}
+
+// Build the stylesheet with the configured engine, once the pages are rendered
+pub fn build_stylesheet(config: &Config) -> Result<(), BuildError> {
+    let css = &config.css;
+    let input_path = config.paths.template_dir.join(&css.input);
+    let output_path = config.paths.output_dir.join(&css.output);
+
+    match css.engine {
+        CssEngine::None => Ok(()),
+        CssEngine::Tailwind if which::which("npx").is_ok() => {
+            build_with_tailwind_cli(config, &input_path, &output_path)
+        }
+        CssEngine::Tailwind => {
+            warn!("`npx` not found in PATH. Falling back to the native CSS engine");
+            build_native(config, &input_path, &output_path)
+        }
+        CssEngine::Native => build_native(config, &input_path, &output_path),
+    }
+}
+
+fn build_native(config: &Config, input_path: &Path, output_path: &Path) -> Result<(), BuildError> {
+    let css_error = |path: &Path, line: Option<usize>, message: String| BuildError::Css {
+        path: path.to_path_buf(),
+        line,
+        message,
+    };
+
+    let input = if input_path.exists() {
+        fs::read_to_string(input_path).map_err(|e| BuildError::io(input_path, e))?
+    } else {
+        info!(
+            "Input stylesheet {:?} not found. Generating the base styles and utilities",
+            input_path
+        );
+        DEFAULT_INPUT.to_string()
+    };
+    // Check the input on its own, for errors to point to its lines
+    StyleSheet::parse(&input, ParserOptions::default()).map_err(|e| {
+        let line = e.loc.as_ref().map(|loc| loc.line as usize + 1);
+        css_error(input_path, line, e.kind.to_string())
+    })?;
+
+    let generator = Generator::new(&config.css.theme)
+        .map_err(|e| css_error(&config.paths.config_file, None, e))?;
+    let candidates = collect_candidates(&config.paths.output_dir, &config.paths.template_dir)?;
+    let (components, utilities) = generator.generate(&candidates);
+    let mut stylesheet = expand_directives(&input, &components, &utilities);
+    if config.css.minify {
+        stylesheet = minify_css(&stylesheet, output_path)
+            .map_err(|e| css_error(output_path, None, format!("invalid generated CSS: {}", e)))?;
+    }
+
+    // Leave the stylesheet untouched when nothing changed
+    if fs::read_to_string(output_path).ok().as_deref() == Some(stylesheet.as_str()) {
+        info!("Stylesheet {:?} is up to date", output_path);
+        return Ok(());
+    }
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent).map_err(|e| BuildError::io(parent, e))?;
+    }
+    fs::write(output_path, stylesheet).map_err(|e| BuildError::io(output_path, e))?;
+    info!("Stylesheet written to {:?}", output_path);
+    Ok(())
+}
+
+// Build the stylesheet with the Tailwind CLI (tailwind.config.js within the template directory)
+// NOTE: side effect of spawning a child process to execute tailwind
+fn build_with_tailwind_cli(
+    config: &Config,
+    input_path: &Path,
+    output_path: &Path,
+) -> Result<(), BuildError> {
+    let working_dir = &config.paths.template_dir;
+    // The CLI runs within the template directory, so the paths (relative to the current
+    // directory when the configuration path is) are passed as absolute paths
+    let absolute = |path: &Path| std::path::absolute(path).map_err(|e| BuildError::io(path, e));
+    let mut args = vec![
+        "tailwindcss".to_string(),
+        "-i".to_string(),
+        absolute(input_path)?.display().to_string(),
+        "-o".to_string(),
+        absolute(output_path)?.display().to_string(),
+    ];
+    if config.css.minify {
+        args.push("--minify".to_string());
+    }
+    info!("Running command: npx {}", args.join(" "));
+
+    let output = Command::new("npx")
+        .args(&args)
+        .current_dir(working_dir)
+        .output()
+        .map_err(|e| BuildError::io(working_dir, e))?;
+    info!("Command completed with status: {}", output.status);
+    if !output.status.success() {
+        debug!("stdout:\n{}", String::from_utf8_lossy(&output.stdout));
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let mut message = format!("Tailwind CLI failed ({})", output.status);
+        if !stderr.trim().is_empty() {
+            message = format!("{}: {}", message, stderr.trim());
+        }
+        return Err(BuildError::Css {
+            path: input_path.to_path_buf(),
+            line: None,
+            message,
+        });
+    }
+
+    info!("Tailwind build succeeded");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utilities(classes: &[&str]) -> String {
+        let generator = Generator::new(&Theme::default()).unwrap();
+        let candidates = classes.iter().map(|class| class.to_string()).collect();
+        generator.generate(&candidates).1
+    }
+
+    #[test]
+    fn class_attributes_quoted_and_unquoted() {
+        let html = r#"<div class="flex py-0.5"><p class='mt-2'>a</p><a class=underline>b</a><span data-class="ignored" class=font-bold></span></div>"#;
+        assert_eq!(
+            class_attributes(html),
+            vec!["flex py-0.5", "mt-2", "underline", "font-bold"]
+        );
+    }
+
+    #[test]
+    fn collects_candidates_from_pages_and_templates() {
+        let dir = std::env::temp_dir().join(format!("ssg-css-candidates-{}", std::process::id()));
+        let (output_dir, template_dir) = (dir.join("build"), dir.join("templates"));
+        fs::create_dir_all(&output_dir).unwrap();
+        fs::create_dir_all(&template_dir).unwrap();
+        fs::write(
+            output_dir.join("index.html"),
+            r#"<p class="py-0.5 text-sm">Text with class=words</p>"#,
+        )
+        .unwrap();
+        fs::write(
+            template_dir.join("menu.js"),
+            "menu.className = 'hidden group-[.menu-open]:flex';",
+        )
+        .unwrap();
+
+        let candidates = collect_candidates(&output_dir, &template_dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        for class in ["py-0.5", "text-sm", "hidden", "group-[.menu-open]:flex"] {
+            assert!(candidates.contains(class), "missing {}", class);
+        }
+        assert!(!candidates.contains("words"));
+    }
+
+    #[test]
+    fn escapes_selectors() {
+        assert_eq!(escape_class("sm:flex"), "sm\\:flex");
+        assert_eq!(escape_class("py-0.5"), "py-0\\.5");
+        assert_eq!(escape_class("w-1/2"), "w-1\\/2");
+        assert_eq!(
+            escape_class("group-[.menu-open]:flex"),
+            "group-\\[\\.menu-open\\]\\:flex"
+        );
+        assert_eq!(escape_class("2xl:p-4"), "\\32 xl\\:p-4");
+    }
+
+    #[test]
+    fn generates_utilities() {
+        let css = utilities(&["py-0.5", "-mt-4", "w-1/2"]);
+        assert!(
+            css.contains(".py-0\\.5 {\n  padding-top: 0.125rem;\n  padding-bottom: 0.125rem;\n}")
+        );
+        assert!(css.contains(".-mt-4 {\n  margin-top: -1rem;\n}"));
+        assert!(css.contains(".w-1\\/2 {\n  width: 50%;\n}"));
+    }
+
+    #[test]
+    fn responsive_and_dark_variants() {
+        let css = utilities(&["dark:text-white", "md:hover:bg-red-500", "sm:flex"]);
+        assert!(css.contains("@media (min-width: 640px) {\n.sm\\:flex {\n  display: flex;\n}\n}"));
+        assert!(css.contains("@media (min-width: 768px) {\n.md\\:hover\\:bg-red-500:hover {"));
+        assert!(css.contains("@media (prefers-color-scheme: dark) {\n.dark\\:text-white {"));
+        // screens in order of their width, then dark
+        let sm = css.find("640px").unwrap();
+        let md = css.find("768px").unwrap();
+        let dark = css.find("prefers-color-scheme").unwrap();
+        assert!(sm < md && md < dark);
+    }
+
+    #[test]
+    fn group_variant() {
+        let css = utilities(&["group-[.menu-open]:flex"]);
+        assert!(css
+            .contains(".group.menu-open .group-\\[\\.menu-open\\]\\:flex {\n  display: flex;\n}"));
+    }
+
+    #[test]
+    fn ignores_unknown_classes() {
+        assert_eq!(
+            utilities(&["foo-bar", "hover:unknown", "unknown:flex", "-flex"]),
+            ""
+        );
+    }
+
+    #[test]
+    fn screens_are_sorted_by_width() {
+        let theme = Theme {
+            screens: BTreeMap::from([
+                ("3xl".to_string(), "120rem".to_string()),
+                ("xs".to_string(), "400px".to_string()),
+            ]),
+            ..Default::default()
+        };
+        let generator = Generator::new(&theme).unwrap();
+        let names: Vec<&str> = generator
+            .screens
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(names, ["xs", "sm", "md", "lg", "xl", "2xl", "3xl"]);
+    }
+
+    #[test]
+    fn rejects_invalid_screen_widths() {
+        for width in ["NaNpx", "infpx", "wide", "10vw"] {
+            let theme = Theme {
+                screens: BTreeMap::from([("xs".to_string(), width.to_string())]),
+                ..Default::default()
+            };
+            assert!(Generator::new(&theme).is_err(), "{}", width);
+        }
+    }
+}
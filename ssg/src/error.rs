@@ -0,0 +1,226 @@
+// Errors that can occur while building the site. Each error keeps track of the file it came
+// from (and the line, where known) so that every error of a build can be collected and
+// reported together in a summary, rather than stopping at the first one
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum BuildError {
+    // The configuration YAML file could not be read, or is invalid
+    Config {
+        path: PathBuf,
+        line: Option<usize>,
+        message: String,
+    },
+    // A file or directory could not be read or written
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    // The frontmatter of a markdown file is invalid
+    Frontmatter {
+        path: PathBuf,
+        line: Option<usize>,
+        message: String,
+    },
+    // The markdown content could not be converted to HTML
+    Markdown {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    // A template failed to load (e.g. a syntax error) or to render a page
+    Template {
+        path: Option<PathBuf>,
+        message: String,
+    },
+    // An image could not be encoded
+    Image {
+        path: PathBuf,
+        message: String,
+    },
+    // The stylesheet could not be built (e.g. invalid CSS within the input stylesheet)
+    Css {
+        path: PathBuf,
+        line: Option<usize>,
+        message: String,
+    },
+    // An external processor (from the `processors` configuration) failed
+    Processor {
+        name: String,
+        message: String,
+    },
+}
+
+impl BuildError {
+    pub fn io(path: &Path, source: io::Error) -> Self {
+        BuildError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    // Tera wraps the actual cause of an error (e.g. the syntax error with its line & column)
+    // within the error it returns, so include every error of the chain in the message
+    pub fn template(path: Option<&Path>, error: &tera::Error) -> Self {
+        BuildError::Template {
+            path: path.map(Path::to_path_buf),
+            message: error_chain(error).trim().to_string(),
+        }
+    }
+
+    // Invalid configuration YAML file
+    pub fn config(path: &Path, error: &serde_yaml::Error) -> Self {
+        BuildError::Config {
+            path: path.to_path_buf(),
+            line: error.location().map(|location| location.line()),
+            message: yaml_message(error),
+        }
+    }
+
+    // Invalid frontmatter within a markdown file. first_line is the line of the file the
+    // frontmatter starts on, as the error location is relative to the frontmatter itself
+    pub fn frontmatter(path: &Path, first_line: usize, error: &serde_yaml::Error) -> Self {
+        BuildError::Frontmatter {
+            path: path.to_path_buf(),
+            line: error
+                .location()
+                .map(|location| first_line + location.line() - 1),
+            message: yaml_message(error),
+        }
+    }
+
+    // File & line the error originated from
+    pub fn location(&self) -> (Option<&Path>, Option<usize>) {
+        match self {
+            BuildError::Config { path, line, .. }
+            | BuildError::Frontmatter { path, line, .. }
+            | BuildError::Css { path, line, .. } => (Some(path), *line),
+            BuildError::Markdown { path, line, .. } => (Some(path), Some(*line)),
+            BuildError::Io { path, .. } | BuildError::Image { path, .. } => (Some(path), None),
+            BuildError::Template { path, .. } => (path.as_deref(), None),
+            BuildError::Processor { .. } => (None, None),
+        }
+    }
+
+    fn description(&self) -> String {
+        match self {
+            BuildError::Config { message, .. } => format!("invalid configuration: {}", message),
+            BuildError::Io { source, .. } => source.to_string(),
+            BuildError::Frontmatter { message, .. } => format!("invalid frontmatter: {}", message),
+            BuildError::Markdown { message, .. } => message.clone(),
+            BuildError::Template { message, .. } => format!("template error: {}", message),
+            BuildError::Image { message, .. } => format!("image error: {}", message),
+            BuildError::Css { message, .. } => format!("CSS error: {}", message),
+            BuildError::Processor { name, message } => {
+                format!("processor `{}` failed: {}", name, message)
+            }
+        }
+    }
+
+    // Format the error with its path relative to the project root (for the build summary)
+    pub fn display_relative(&self, root: &Path) -> String {
+        match self.location() {
+            (Some(path), line) => {
+                let path = path.strip_prefix(root).unwrap_or(path).display();
+                match line {
+                    Some(line) => format!("{}:{}: {}", path, line, self.description()),
+                    None => format!("{}: {}", path, self.description()),
+                }
+            }
+            (None, _) => self.description(),
+        }
+    }
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.display_relative(Path::new("")))
+    }
+}
+
+impl Error for BuildError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BuildError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+// serde_yaml appends the location to its messages, which is reported separately (and would be
+// wrong for frontmatter, being relative to the start of the frontmatter). Syntax errors may
+// carry more than one location (e.g. "did not find expected key at line 3 column 1, while
+// parsing a block mapping at line 1 column 1"), so every one of them is removed
+fn yaml_message(error: &serde_yaml::Error) -> String {
+    let mut message = error.to_string();
+    let mut start = 0;
+    while let Some(index) = message[start..].find(" at line ") {
+        let index = start + index;
+        match location_length(&message[index..]) {
+            Some(length) => message.replace_range(index..index + length, ""),
+            None => start = index + 1,
+        }
+    }
+    message
+}
+
+// Length of the " at line N column M" at the start of the text, if any
+fn location_length(text: &str) -> Option<usize> {
+    let digits =
+        |text: &str| text.len() - text.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let rest = text.strip_prefix(" at line ")?;
+    let line_digits = digits(rest);
+    let rest = rest[line_digits..].strip_prefix(" column ")?;
+    let column_digits = digits(rest);
+    (line_digits > 0 && column_digits > 0)
+        .then(|| " at line ".len() + line_digits + " column ".len() + column_digits)
+}
+
+// Join the messages of an error and all of its sources
+pub fn error_chain(error: &dyn Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        message.push_str(": ");
+        message.push_str(&error.to_string());
+        source = error.source();
+    }
+    message
+}
+
+// Print every error collected (e.g. during the build)
+pub fn print_summary(heading: &str, errors: &[BuildError], root: &Path) {
+    eprintln!("{} with {} error(s):", heading, errors.len());
+    for error in errors {
+        eprintln!("  {}", error.display_relative(root));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(yaml: &str) -> String {
+        yaml_message(&serde_yaml::from_str::<serde_yaml::Value>(yaml).unwrap_err())
+    }
+
+    #[test]
+    fn yaml_message_strips_every_location() {
+        assert_eq!(
+            message("a: [1, 2\nb: 3"),
+            "did not find expected ',' or ']', while parsing a flow sequence"
+        );
+    }
+
+    #[test]
+    fn yaml_message_keeps_other_text() {
+        let error = serde_yaml::from_str::<u32>("at line one").unwrap_err();
+        assert_eq!(
+            yaml_message(&error),
+            "invalid type: string \"at line one\", expected u32"
+        );
+    }
+}
@@ -0,0 +1,473 @@
+// Audits the external links (outbound URLs) found within the markdown content. The audit is
+// opt-in (`ssg check --external`) so that regular builds never touch the network
+//
+// Requests go through the HttpClient trait, so the checker can be pointed at a different client
+// (e.g. one talking to a local stub server) instead of the real network
+use crate::cache;
+use log::{info, warn};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{Error, Result};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// External link audit settings from the configuration YAML (the `link_check` section)
+// - allow_domains: when not empty, only links to these domains (or their subdomains) are checked
+// - deny_domains: links to these domains (or their subdomains) are never checked
+// - cache_hours: how long a link that was found to work is trusted before being checked again.
+//   Broken links are always checked again
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LinkCheckConfig {
+    pub timeout_secs: u64,
+    pub retries: u32,
+    pub concurrency: usize,
+    pub cache_hours: u64,
+    pub allow_domains: Vec<String>,
+    pub deny_domains: Vec<String>,
+}
+
+impl Default for LinkCheckConfig {
+    fn default() -> Self {
+        LinkCheckConfig {
+            timeout_secs: 10,
+            retries: 2,
+            concurrency: 8,
+            cache_hours: 24,
+            allow_domains: Vec::new(),
+            deny_domains: Vec::new(),
+        }
+    }
+}
+
+// Minimal HTTP interface used by the checker: a request with the given method (HEAD or GET).
+// Returns the status code of the response (after following redirects), or an error message if
+// no response was received (DNS failure, timeout, refused connection, etc.)
+pub trait HttpClient: Sync {
+    fn request(&self, method: &str, url: &str) -> std::result::Result<u16, String>;
+}
+
+// HTTP client backed by ureq
+pub struct UreqClient {
+    agent: ureq::Agent,
+}
+
+impl UreqClient {
+    pub fn new(timeout: Duration) -> Self {
+        UreqClient {
+            agent: ureq::AgentBuilder::new()
+                .timeout(timeout)
+                .redirects(10)
+                .user_agent(concat!("ssg-link-check/", env!("CARGO_PKG_VERSION")))
+                .build(),
+        }
+    }
+}
+
+impl HttpClient for UreqClient {
+    fn request(&self, method: &str, url: &str) -> std::result::Result<u16, String> {
+        match self.agent.request(method, url).call() {
+            Ok(response) => Ok(response.status()),
+            Err(ureq::Error::Status(status, _)) => Ok(status),
+            Err(ureq::Error::Transport(transport)) => Err(transport.to_string()),
+        }
+    }
+}
+
+// Status of a URL. Some servers do not support HEAD requests (or block them), so those are
+// retried with GET
+fn status(client: &dyn HttpClient, url: &str) -> std::result::Result<u16, String> {
+    match client.request("HEAD", url)? {
+        403 | 405 | 501 => client.request("GET", url),
+        status => Ok(status),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkStatus {
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    // seconds since the UNIX epoch
+    pub checked_at: u64,
+}
+
+impl LinkStatus {
+    pub fn is_ok(&self) -> bool {
+        self.status.is_some_and(|status| status < 400)
+    }
+}
+
+// Results of previous audits, saved in the build folder
+const LINK_CACHE_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LinkCache {
+    version: u32,
+    links: HashMap<String, LinkStatus>,
+}
+
+#[derive(Debug)]
+pub struct BrokenExternalLink {
+    pub url: String,
+    pub sources: Vec<PathBuf>,
+    pub status: LinkStatus,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+// Host of an http(s) URL (without the port or any credentials)
+fn host(url: &str) -> Option<&str> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit('@').next()?;
+    Some(host.split(':').next().unwrap_or(host))
+}
+
+fn matches_domain(host: &str, domains: &[String]) -> bool {
+    domains.iter().any(|domain| {
+        let domain = domain.trim_start_matches('.');
+        host.eq_ignore_ascii_case(domain)
+            || host
+                .to_ascii_lowercase()
+                .ends_with(&format!(".{}", domain.to_ascii_lowercase()))
+    })
+}
+
+impl LinkCheckConfig {
+    fn should_check(&self, url: &str) -> bool {
+        let Some(host) = host(url) else {
+            return false;
+        };
+        if matches_domain(host, &self.deny_domains) {
+            return false;
+        }
+        self.allow_domains.is_empty() || matches_domain(host, &self.allow_domains)
+    }
+}
+
+fn load_link_cache(path: &Path) -> HashMap<String, LinkStatus> {
+    let Ok(data) = fs::read_to_string(path) else {
+        return HashMap::new();
+    };
+    match serde_json::from_str::<LinkCache>(&data) {
+        Ok(cache) if cache.version == LINK_CACHE_VERSION => cache.links,
+        _ => {
+            warn!("Discarding unreadable link check cache {:?}", path);
+            HashMap::new()
+        }
+    }
+}
+
+fn save_link_cache(path: &Path, links: HashMap<String, LinkStatus>) -> Result<()> {
+    let cache = LinkCache {
+        version: LINK_CACHE_VERSION,
+        links,
+    };
+    let json = serde_json::to_string(&cache).map_err(Error::other)?;
+    cache::write_file_atomic(path, json.as_bytes())
+}
+
+// Check a single URL, retrying (with an increasing delay) when no response was received or the
+// server responded with a temporary error
+fn check_url(client: &dyn HttpClient, url: &str, retries: u32) -> LinkStatus {
+    let mut attempt = 0;
+    loop {
+        let result = status(client, url);
+        let is_temporary = match &result {
+            Ok(status) => *status == 429 || *status >= 500,
+            Err(_) => true,
+        };
+        if !is_temporary || attempt >= retries {
+            let (status, error) = match result {
+                Ok(status) => (Some(status), None),
+                Err(error) => (None, Some(error)),
+            };
+            return LinkStatus {
+                status,
+                error,
+                checked_at: now(),
+            };
+        }
+
+        attempt += 1;
+        info!("Retrying {} (attempt {} of {})", url, attempt, retries);
+        thread::sleep(Duration::from_millis(500 * u64::from(attempt)));
+    }
+}
+
+// Check every external link (url -> the source files linking to it) concurrently. Results are
+// cached in the build folder. Returns the broken links, sorted by URL
+pub fn check_external_links(
+    links: &BTreeMap<String, Vec<PathBuf>>,
+    config: &LinkCheckConfig,
+    client: &dyn HttpClient,
+    cache_path: &Path,
+) -> Result<Vec<BrokenExternalLink>> {
+    let mut cached_links = load_link_cache(cache_path);
+    let max_age = config.cache_hours * 60 * 60;
+    let checked_at = now();
+
+    let urls_to_check: Vec<&String> = links
+        .keys()
+        .filter(|url| {
+            if !config.should_check(url) {
+                info!("Skipping link {} (not an allowed domain)", url);
+                return false;
+            }
+            // links that worked recently are not checked again
+            !cached_links.get(*url).is_some_and(|status| {
+                status.is_ok() && checked_at.saturating_sub(status.checked_at) < max_age
+            })
+        })
+        .collect();
+    info!(
+        "Checking {} external link(s) ({} cached)",
+        urls_to_check.len(),
+        links.len() - urls_to_check.len()
+    );
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(config.concurrency.max(1))
+        .build()
+        .map_err(Error::other)?;
+    let results: Vec<(String, LinkStatus)> = pool.install(|| {
+        urls_to_check
+            .par_iter()
+            .map(|url| ((*url).clone(), check_url(client, url, config.retries)))
+            .collect()
+    });
+    cached_links.extend(results);
+
+    let broken_links = links
+        .iter()
+        .filter(|(url, _)| config.should_check(url))
+        .filter_map(|(url, sources)| {
+            let status = cached_links.get(url)?;
+            (!status.is_ok()).then(|| BrokenExternalLink {
+                url: url.clone(),
+                sources: sources.clone(),
+                status: status.clone(),
+            })
+        })
+        .collect();
+
+    // forget the links that are no longer part of the site
+    cached_links.retain(|url, _| links.contains_key(url));
+    save_link_cache(cache_path, cached_links)?;
+
+    Ok(broken_links)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    type Response = std::result::Result<u16, String>;
+
+    // Responds from a queue of responses for every request (method & URL), repeating the last
+    // one, and records the requests it received
+    #[derive(Default)]
+    struct StubClient {
+        responses: Mutex<HashMap<(String, String), VecDeque<Response>>>,
+        requests: Mutex<Vec<(String, String)>>,
+    }
+
+    impl StubClient {
+        fn respond(
+            self,
+            method: &str,
+            url: &str,
+            responses: &[std::result::Result<u16, &str>],
+        ) -> Self {
+            let responses = responses
+                .iter()
+                .map(|response| response.map_err(str::to_string))
+                .collect();
+            self.responses
+                .lock()
+                .unwrap()
+                .insert((method.to_string(), url.to_string()), responses);
+            self
+        }
+
+        fn requests(&self) -> Vec<(String, String)> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    impl HttpClient for StubClient {
+        fn request(&self, method: &str, url: &str) -> Response {
+            let key = (method.to_string(), url.to_string());
+            self.requests.lock().unwrap().push(key.clone());
+            let mut responses = self.responses.lock().unwrap();
+            let Some(queue) = responses.get_mut(&key) else {
+                return Err("no response".to_string());
+            };
+            if queue.len() > 1 {
+                queue.pop_front().unwrap()
+            } else {
+                queue.front().cloned().unwrap()
+            }
+        }
+    }
+
+    fn request(method: &str, url: &str) -> (String, String) {
+        (method.to_string(), url.to_string())
+    }
+
+    // Cache file unique to the test, within the temporary directory
+    fn cache_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "ssg-link-cache-{}-{}.json",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn links(urls: &[&str]) -> BTreeMap<String, Vec<PathBuf>> {
+        urls.iter()
+            .map(|url| (url.to_string(), vec![PathBuf::from("content/post/a.md")]))
+            .collect()
+    }
+
+    #[test]
+    fn retries_server_errors() {
+        let url = "https://example.com/";
+        let client = StubClient::default().respond("HEAD", url, &[Ok(503), Ok(200)]);
+        let status = check_url(&client, url, 2);
+        assert_eq!(status.status, Some(200));
+        assert_eq!(client.requests().len(), 2);
+    }
+
+    #[test]
+    fn retries_timeouts_until_out_of_retries() {
+        let url = "https://example.com/";
+        let client = StubClient::default().respond("HEAD", url, &[Err("timed out")]);
+        let status = check_url(&client, url, 1);
+        assert_eq!(status.status, None);
+        assert_eq!(status.error.as_deref(), Some("timed out"));
+        assert!(!status.is_ok());
+        assert_eq!(client.requests().len(), 2);
+    }
+
+    #[test]
+    fn does_not_retry_client_errors() {
+        let url = "https://example.com/missing";
+        let client = StubClient::default().respond("HEAD", url, &[Ok(404)]);
+        let status = check_url(&client, url, 2);
+        assert_eq!(status.status, Some(404));
+        assert_eq!(client.requests(), vec![request("HEAD", url)]);
+    }
+
+    #[test]
+    fn falls_back_to_get_when_head_is_not_allowed() {
+        let url = "https://example.com/";
+        let client = StubClient::default()
+            .respond("HEAD", url, &[Ok(405)])
+            .respond("GET", url, &[Ok(200)]);
+        let status = check_url(&client, url, 0);
+        assert_eq!(status.status, Some(200));
+        assert_eq!(
+            client.requests(),
+            vec![request("HEAD", url), request("GET", url)]
+        );
+    }
+
+    #[test]
+    fn allow_and_deny_lists() {
+        let config = LinkCheckConfig {
+            allow_domains: vec!["example.com".to_string()],
+            deny_domains: vec!["private.example.com".to_string()],
+            ..Default::default()
+        };
+        assert!(config.should_check("https://example.com/page"));
+        assert!(config.should_check("https://www.Example.com:8080/page"));
+        assert!(!config.should_check("https://private.example.com/page"));
+        assert!(!config.should_check("https://api.private.example.com/"));
+        assert!(!config.should_check("https://notexample.com/"));
+        assert!(!config.should_check("mailto:someone@example.com"));
+        assert!(LinkCheckConfig::default().should_check("http://user@other.org/"));
+    }
+
+    #[test]
+    fn skips_links_outside_the_allowed_domains() {
+        let config = LinkCheckConfig {
+            deny_domains: vec!["denied.com".to_string()],
+            ..Default::default()
+        };
+        let client = StubClient::default().respond("HEAD", "https://ok.com/", &[Ok(200)]);
+        let path = cache_path("deny");
+        let broken = check_external_links(
+            &links(&["https://ok.com/", "https://denied.com/"]),
+            &config,
+            &client,
+            &path,
+        )
+        .unwrap();
+        assert!(broken.is_empty());
+        assert_eq!(client.requests(), vec![request("HEAD", "https://ok.com/")]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn checks_cached_links_again_once_expired_or_broken() {
+        let config = LinkCheckConfig {
+            cache_hours: 1,
+            ..Default::default()
+        };
+        let (recent, expired, broken) = (
+            "https://recent.com/",
+            "https://expired.com/",
+            "https://broken.com/",
+        );
+        let cached = |status, age| LinkStatus {
+            status: Some(status),
+            error: None,
+            checked_at: now() - age,
+        };
+        let path = cache_path("expiry");
+        save_link_cache(
+            &path,
+            HashMap::from([
+                (recent.to_string(), cached(200, 60)),
+                (expired.to_string(), cached(200, 2 * 60 * 60)),
+                (broken.to_string(), cached(404, 60)),
+            ]),
+        )
+        .unwrap();
+
+        let client = StubClient::default()
+            .respond("HEAD", expired, &[Ok(200)])
+            .respond("HEAD", broken, &[Ok(404)]);
+        let broken_links =
+            check_external_links(&links(&[recent, expired, broken]), &config, &client, &path)
+                .unwrap();
+        let mut requests = client.requests();
+        requests.sort();
+        assert_eq!(
+            requests,
+            vec![request("HEAD", broken), request("HEAD", expired)]
+        );
+        assert_eq!(broken_links.len(), 1);
+        assert_eq!(broken_links[0].url, broken);
+        assert_eq!(broken_links[0].status.status, Some(404));
+
+        // links that are no longer part of the site are forgotten
+        check_external_links(&links(&[recent]), &config, &client, &path).unwrap();
+        assert_eq!(load_link_cache(&path).len(), 1);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -1,21 +1,41 @@
+use assets::AssetManifest;
 use cache::CacheContext;
-use env_logger;
+use config::Config;
+use error::BuildError;
 use gray_matter::engine::YAML;
 use gray_matter::Matter;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
+use minify::Minifier;
 use minify_html::{minify, Cfg};
+use processors::{ProcessorCache, Stage};
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::ffi::OsStr;
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::time::Duration;
 use tera::{Context, Tera};
 use walkdir::WalkDir;
 
+mod assets;
 mod cache;
+mod check;
+mod cli;
+mod compress;
+mod config;
+mod css;
+mod error;
+mod linkcheck;
+mod minify;
 mod parser;
 mod path;
+mod processors;
+mod related;
 mod resources;
+mod search;
+mod seo;
+mod watch;
 
 // Match raw frontmatter input directly before being further parsed
 // into more appropriate page type (separate frontmatter and metadata)
@@ -25,41 +45,13 @@ struct Frontmatter {
     date: Option<String>,
     tags: Option<Vec<String>>,
     description: Option<String>,
-}
-
-// Retrieve configuration information from the configuration YAML file
-#[derive(Debug, Deserialize)]
-struct Config {
-    metadata: SiteMetadata,
-    paths: Paths,
-    build: Build,
-}
-
-// Metadata retrieved from the configuration YAML file
-#[derive(Debug, Deserialize)]
-struct SiteMetadata {
-    base_url: String,
-    author: String,
-    description: String,
-}
-
-// Paths for content, template, output build, and the static resourees. Specified as the path
-// relative to the configuration YAML file. NOT the Rust project directories
-#[derive(Debug, Deserialize)]
-struct Paths {
-    content_dir: PathBuf,
-    template_dir: PathBuf,
-    output_dir: PathBuf,
-    static_dir: PathBuf,
-}
-
-// Build configuration specifications from configuration YAML.
-// TODO: Implement sitemap generation feature
-#[derive(Debug, Deserialize)]
-struct Build {
-    minify_html: bool,
-    generate_sitemap: bool,
-    cache: bool,
+    // Image for link previews (relative to the static folder, or an absolute URL)
+    image: Option<String>,
+    // Drafts are left out of the site unless `build.drafts` is enabled
+    draft: Option<bool>,
+    // Free-form values for the templates (available as `page.extra`)
+    #[serde(default)]
+    extra: tera::Map<String, tera::Value>,
 }
 
 // A page is one of: Index (for the main page), a Page ("supporting" information page), a Post
@@ -86,7 +78,34 @@ struct Page {
     description: Option<String>,
     tags: Option<Vec<String>>,
     date: Option<String>,
+    image: Option<String>,
     content: String,
+    extra: tera::Map<String, tera::Value>,
+    // Number of words of the content (without the code blocks), and the minutes it takes to
+    // read them
+    word_count: usize,
+    reading_time: usize,
+    // HTML summary of the content, for listings and the description fallback
+    summary: String,
+    // The content rendered to HTML (rendered once, for the summary and the page itself)
+    #[serde(skip)]
+    html: String,
+    // Posts related to a post (by shared tags and content), empty for other pages
+    related: Vec<related::RelatedPost>,
+    // Set when the page needs to be rendered during this build (false if it is unchanged since
+    // the last cached build)
+    #[serde(skip)]
+    rebuild: bool,
+    // Directory of a page bundle (a post stored as `<section>/<name>/index.md` along with its
+    // images and other assets)
+    #[serde(skip)]
+    bundle: Option<PathBuf>,
+    // Markdown file the page was generated from, and the line its content starts on (after the
+    // frontmatter). Used to report errors
+    #[serde(skip)]
+    source: PathBuf,
+    #[serde(skip)]
+    content_line: usize,
 }
 
 #[derive(Debug)]
@@ -133,161 +152,184 @@ impl Site {
         }
     }
 
-    fn generate_page(&self, page: &Page, tera: &Tera) -> Result<(), Box<dyn std::error::Error>> {
-        let html_output = parser::parse_markdown_with_tailwind(&page.content, tera);
+    fn get_output_path(&self, page: &Page) -> PathBuf {
+        let output_filename = format!("{}.html", page.name);
+        Path::new(&self.configuration.paths.output_dir).join(output_filename)
+    }
 
+    fn generate_page(
+        &self,
+        page: &Page,
+        tera: &Tera,
+        static_rules: &resources::StaticRules,
+        assets: &AssetManifest,
+    ) -> Result<(), BuildError> {
         let mut context = Context::new();
         context.insert("title", &page.title);
         context.insert("date", &page.date);
-        context.insert("content", &html_output);
+        context.insert("word_count", &page.word_count);
+        context.insert("reading_time", &page.reading_time);
+        context.insert("content", &page.html);
         context.insert("author", &self.configuration.metadata.author);
-        context.insert("description", &self.configuration.metadata.description);
+        // The description of the page, falling back to a summary of its content
+        let seo = seo::page_seo(&self.configuration.metadata, static_rules, assets, page);
+        context.insert("description", &seo.description);
+        context.insert(
+            "json_ld",
+            &seo::json_ld(&self.configuration.metadata, page, &seo),
+        );
+        context.insert("seo", &seo);
         context.insert("pages", &self.pages);
         context.insert("posts", &self.posts);
         context.insert("tags", &page.tags);
-        
-        let output_filename = format!("{}.html", page.name);
+        // The site configuration metadata (including the custom `params`) and the page itself
+        // (including the frontmatter `extra` values)
+        context.insert("site", &self.configuration.metadata);
+        context.insert("page", page);
+        // URL of the search index (when search is enabled), for partials/search.html
+        context.insert("search_index", &self.configuration.search.index_url());
+
         let html_template_file = Site::get_template_name(page);
-        let rendered = tera.render(&format!("{}.html", html_template_file), &context)?;
+        let rendered = tera
+            .render(&format!("{}.html", html_template_file), &context)
+            .map_err(|e| BuildError::template(Some(&page.source), &e))?;
 
-        let output_path = Path::new(&self.configuration.paths.output_dir).join(output_filename);
-        if self.configuration.build.minify_html {
+        let output_path = self.get_output_path(page);
+        let written = if self.configuration.build.minify_html {
             let minified = minify(
                 rendered.as_bytes(),
                 &Cfg {
                     minify_js: true,
-                    minify_css: false,
+                    minify_css: self.configuration.build.minify_css,
                     ..Default::default()
                 },
             );
-            fs::write(output_path, minified)?;
+            fs::write(&output_path, minified)
         } else {
-            fs::write(output_path, rendered)?;
-        }
+            fs::write(&output_path, rendered)
+        };
 
-        Ok(())
+        written.map_err(|e| BuildError::io(&output_path, e))
     }
 }
 
-// Reading YAML configuration files
-fn load_yaml_config(file_path: &PathBuf) -> Result<Config, Box<dyn std::error::Error>> {
-    let content = fs::read_to_string(file_path)?;
-    let config: Config = serde_yaml::from_str(&content)?;
-    Ok(config)
+// Render the markdown content of the page to HTML
+fn render_markdown(
+    page: &Page,
+    tera: &Tera,
+    static_rules: &resources::StaticRules,
+    assets: &AssetManifest,
+) -> Result<String, BuildError> {
+    // Relative links within a page bundle point to the assets copied next to the page
+    let bundle = page.bundle.as_ref().map(|dir| parser::Bundle {
+        dir,
+        url: &page.name,
+    });
+    parser::parse_markdown_with_tailwind(
+        &page.content,
+        tera,
+        static_rules,
+        assets,
+        bundle.as_ref(),
+        &page.source,
+        page.content_line,
+    )
 }
 
-// Resolves all directory paths relative to the configuration YAML file to
-// to change to absbolute paths in the project
-fn reconcile_configuration_directory_paths(base_path: &Path, config: Paths) -> Paths {
-    Paths {
-        content_dir: base_path.join(config.content_dir),
-        template_dir: base_path.join(config.template_dir),
-        output_dir: base_path.join(config.output_dir),
-        static_dir: base_path.join(config.static_dir),
+// The summary of a page (HTML): its content before the `<!-- more -->` marker, or else the
+// first words of its content
+fn page_summary(html: &str, summary_words: usize) -> String {
+    match parser::content_before_more(html) {
+        // closes the elements left open by a marker within a paragraph
+        Some(summary) => parser::truncate_html(summary, usize::MAX),
+        None => parser::truncate_html(html, summary_words),
     }
 }
 
-// Grabs the configuration file relative to the location in the CONFIG_PATH environment variable
-fn retrieve_configuration() -> Config {
-    info!("Retrieving config file");
-
-    let config_path = path::resolve_environment_variable_path("CONFIG_PATH", "../config.yml");
-    let config_dir = config_path.parent().unwrap_or_else(|| Path::new("."));
-    debug!("{:?}", config_path);
-    let mut config = load_yaml_config(&config_path).unwrap();
-
-    config.paths = reconcile_configuration_directory_paths(config_dir, config.paths);
-    debug!("{:?}", config);
-
-    config
-}
-
 fn retrieve_cache(config: &Config) -> CacheContext {
     info!("Retrieving cache JSON file");
     // The cache will exist within the bin folder
     let cache_path = config.paths.output_dir.join("cache.json");
-    CacheContext::load_or_default(cache_path).unwrap()
+    CacheContext::load_or_default(cache_path, &config.paths.root_dir)
 }
 
-// Build styling with Tailwind
-// Assumes Tailwind exists globally on the system (npm install -g)
-//
-// NOTE: side effect of spawning a child process to execute tailwind
-// Retrieve the folder name of a file (as matched to an HTML template)
-fn build_tailwind(site: &Site) -> std::io::Result<()> {
-    let output_path = site
-        .configuration
-        .paths
-        .output_dir
-        .join("static/styles/tailwind.css");
-    let working_dir = site.configuration.paths.template_dir.clone();
-
-    info!("Tailwind build starting");
-    info!("Output path: {:?}", output_path);
-    info!("Working directory: {:?}", working_dir);
-
-    // Check path existence
-    if !working_dir.exists() {
-        error!("Working directory does not exist: {:?}", working_dir);
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            format!("Directory not found: {:?}", working_dir),
-        ));
-    }
-
-    if which::which("npx").is_err() {
-        error!("`npx` not found in PATH!");
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            "npx not found in PATH",
-        ));
-    }
-
-    // Log command being run
-    info!(
-        "Running command: npx tailwindcss -i input.css -o {} --minify",
-        output_path.to_str().unwrap_or("<invalid path>")
-    );
+// Hash of the settings the pages are rendered with (the site metadata, drafts, minification,
+// etc) and of the templates. Every page is rendered again when it changes, as the pages
+// themselves are unchanged. Whether the cache is used is not part of it
+fn render_settings_hash(config: &Config) -> String {
+    let build = &config.build;
+    let settings = serde_json::to_string(&(
+        &config.metadata,
+        (
+            build.minify_html,
+            build.minify_css,
+            build.minify_js,
+            build.generate_sitemap,
+            build.drafts,
+        ),
+        &config.content,
+        &config.static_files,
+        &config.search,
+        &config.related,
+    ))
+    .unwrap_or_default();
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(settings.as_bytes());
 
-    let output = Command::new("npx")
-        .args([
-            "tailwindcss",
-            "-i",
-            "input.css",
-            "-o",
-            output_path.to_str().unwrap(),
-            "--minify",
-        ])
-        .current_dir(&working_dir)
-        .output();
-
-    match output {
-        Ok(output) => {
-            info!("Command completed with status: {}", output.status);
-            if !output.status.success() {
-                error!("Tailwind build failed");
-                error!("stdout:\n{}", String::from_utf8_lossy(&output.stdout));
-                error!("stderr:\n{}", String::from_utf8_lossy(&output.stderr));
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    "Tailwind build failed",
-                ));
-            }
-        }
-        Err(e) => {
-            error!("Failed to spawn command: {}", e);
-            return Err(e);
-        }
+    // The templates loaded by Tera (see load_templates), in a stable order
+    let mut templates: Vec<PathBuf> = WalkDir::new(&config.paths.template_dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().extension() == Some(OsStr::new("html")))
+        .map(|entry| entry.into_path())
+        .collect();
+    templates.sort();
+    for template in templates {
+        hasher.update(template.to_string_lossy().as_bytes());
+        hasher.update(&fs::read(&template).unwrap_or_default());
     }
+    hasher.finalize().to_hex().to_string()
+}
 
-    info!("Tailwind build succeeded");
-    Ok(())
+// Load every template within the template directory
+fn load_templates(template_dir: &Path) -> Result<Tera, BuildError> {
+    let template_filepath = format!("{}/**/*.html", template_dir.to_string_lossy());
+    Tera::new(&template_filepath).map_err(|e| BuildError::template(None, &e))
 }
 
-fn get_template_name(path_to_file: &Path) -> &OsStr {
-    // Retrieve the parent folder name to the file path
-    let folder_path = path_to_file.parent().unwrap();
-    let folder_name = folder_path.file_name().unwrap(); // returns the directory name
+// A page bundle is a directory within a content section holding an index.md file with the
+// page's assets (e.g. content/post/my-post/index.md). Returns the bundle directory if the
+// markdown file is the index of a bundle
+fn get_bundle_dir<'a>(path_to_file: &'a Path, content_dir: &Path) -> Option<&'a Path> {
+    let relative_path = path_to_file.strip_prefix(content_dir).ok()?;
+    if relative_path.components().count() == 3
+        && path_to_file.file_name() == Some(OsStr::new("index.md"))
+    {
+        path_to_file.parent()
+    } else {
+        None
+    }
+}
+
+// The page bundle holding a markdown file other than its index.md (directly, or within one of
+// its subdirectories), if any
+fn get_enclosing_bundle<'a>(path_to_file: &'a Path, content_dir: &Path) -> Option<&'a Path> {
+    path_to_file.ancestors().skip(1).find(|dir| {
+        let bundle_index = dir.join("index.md");
+        bundle_index != path_to_file
+            && bundle_index.is_file()
+            && get_bundle_dir(&bundle_index, content_dir).is_some()
+    })
+}
+
+fn get_template_name<'a>(path_to_file: &'a Path, bundle_dir: Option<&'a Path>) -> &'a OsStr {
+    // Retrieve the parent folder name to the file path (or the folder containing the bundle)
+    let folder_path = match bundle_dir {
+        Some(bundle_dir) => bundle_dir.parent(),
+        None => path_to_file.parent(),
+    };
+    // returns the directory name
+    let folder_name = folder_path.and_then(Path::file_name).unwrap_or_default();
 
     // In the case that the folder name is a page, the template's HTML file will match the name of the markdown file
     // Otherwise, the folder name would match as the specific template (as multiple pages follow the same template)
@@ -300,9 +342,19 @@ fn extract_page_info(
     frontmatter: Frontmatter,
     content: String,
     page_type: PageType,
+    bundle_dir: Option<&Path>,
+    content_line: usize,
 ) -> Page {
-    let name = path.file_stem().unwrap().to_str().unwrap().to_string();
+    // Bundled pages are named after their directory rather than the index.md file
+    let name = match bundle_dir {
+        Some(bundle_dir) => bundle_dir.file_name(),
+        None => path.file_stem(),
+    }
+    .unwrap_or_default()
+    .to_string_lossy()
+    .into_owned();
 
+    let word_count = parser::word_count(&content);
     let page = Page {
         page_type,
         name: name.clone(),
@@ -311,18 +363,196 @@ fn extract_page_info(
         description: frontmatter.description,
         tags: frontmatter.tags,
         date: frontmatter.date,
+        image: frontmatter.image,
         content,
+        extra: frontmatter.extra,
+        word_count,
+        reading_time: 0,
+        summary: String::new(),
+        html: String::new(),
+        related: Vec::new(),
+        rebuild: true,
+        bundle: bundle_dir.map(Path::to_path_buf),
+        source: path.to_path_buf(),
+        content_line,
     };
 
     page
 }
 
+// Post read from a markdown file with the given frontmatter (as YAML), for the tests of the
+// modules working on the posts
+#[cfg(test)]
+fn test_post(path: &str, frontmatter: &str, content: &str) -> Page {
+    extract_page_info(
+        String::new(),
+        Path::new(path),
+        serde_yaml::from_str(frontmatter).unwrap(),
+        content.to_string(),
+        PageType::Post,
+        None,
+        1,
+    )
+}
+
 fn main() -> std::io::Result<()> {
     // Initialize the logger (which uses an environment variable to correspondingly toggle)
     env_logger::init();
 
-    let config = retrieve_configuration();
-    let mut cache_context = retrieve_cache(&config);
+    let args = match cli::parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if args.help {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
+
+    // Pages are rendered and images are optimized on a thread pool (sized to the number of
+    // CPUs by default)
+    if let Some(jobs) = args.jobs {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build_global()
+            .map_err(std::io::Error::other)?;
+    }
+
+    // Watch mode reloads the configuration for every build (and keeps going when it is invalid)
+    if args.command == cli::Command::Watch {
+        watch::watch(&args, |config| build_site(&args, config));
+    }
+
+    let config = match config::retrieve_configuration(args.env.as_deref()) {
+        Ok(config) => config,
+        Err(errors) => {
+            error::print_summary("Failed to load the configuration", &errors, Path::new(""));
+            std::process::exit(1);
+        }
+    };
+    match args.command {
+        cli::Command::Build => {
+            let root_dir = config.paths.root_dir.clone();
+            if let Err(errors) = build_site(&args, config) {
+                error::print_summary("Build failed", &errors, &root_dir);
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        cli::Command::Check => check_site(&args, &config),
+        cli::Command::Config => print_config(&args, &config),
+        cli::Command::Watch => unreachable!("watch mode runs until interrupted"),
+    }
+}
+
+// The configuration is validated before any command runs. Print the effective configuration
+// (with the defaults filled in and the paths resolved) with --print
+fn print_config(args: &cli::Args, config: &Config) -> std::io::Result<()> {
+    if args.print {
+        let yaml = serde_yaml::to_string(config).map_err(std::io::Error::other)?;
+        println!("# environment: {}", config.environment);
+        print!("{}", yaml);
+    } else {
+        println!("Configuration is valid");
+    }
+    Ok(())
+}
+
+// Collect the external links within the markdown content (url -> the files linking to it)
+fn collect_external_links(content_dir: &Path) -> std::io::Result<BTreeMap<String, Vec<PathBuf>>> {
+    let mut links: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for entry in WalkDir::new(content_dir).into_iter().filter_map(Result::ok) {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("md") {
+            continue;
+        }
+
+        let markdown = fs::read_to_string(path)?;
+        let parsed_frontmatter = Matter::<YAML>::new().parse(&markdown);
+        for url in parser::collect_external_links(&parsed_frontmatter.content) {
+            let sources = links.entry(url).or_default();
+            if !sources.iter().any(|source| source == path) {
+                sources.push(path.to_path_buf());
+            }
+        }
+    }
+    Ok(links)
+}
+
+// Check the external links within the content (opt-in, as this requires network access)
+fn check_external_links(config: &Config) -> std::io::Result<usize> {
+    let links = collect_external_links(&config.paths.content_dir)?;
+    info!("Checking {} external link(s)", links.len());
+
+    let link_check = &config.link_check;
+    let client = linkcheck::UreqClient::new(Duration::from_secs(link_check.timeout_secs));
+    let broken_links = linkcheck::check_external_links(
+        &links,
+        link_check,
+        &client,
+        &config.paths.output_dir.join("link-check-cache.json"),
+    )?;
+
+    for broken_link in &broken_links {
+        let reason = match (&broken_link.status.status, &broken_link.status.error) {
+            (Some(status), _) => format!("HTTP {}", status),
+            (None, Some(error)) => error.clone(),
+            (None, None) => "no response".to_string(),
+        };
+        for source in &broken_link.sources {
+            let source = source
+                .strip_prefix(&config.paths.content_dir)
+                .unwrap_or(source);
+            println!("{}: {} ({})", source.display(), broken_link.url, reason);
+        }
+    }
+    Ok(broken_links.len())
+}
+
+// Check the generated site for broken internal links and missing assets (and the external
+// links, with --external), exiting with a non-zero status code if any are found
+fn check_site(args: &cli::Args, config: &Config) -> std::io::Result<()> {
+    let output_dir = &config.paths.output_dir;
+    info!("Checking the generated site in {:?}", output_dir);
+
+    let broken_links = check::check_site(output_dir, &config.metadata.base_url)?;
+    for broken_link in &broken_links {
+        let file = broken_link
+            .file
+            .strip_prefix(output_dir)
+            .unwrap_or(&broken_link.file);
+        println!(
+            "{}:{}:{}: {} ({})",
+            file.display(),
+            broken_link.line,
+            broken_link.column,
+            broken_link.target,
+            broken_link.reason
+        );
+    }
+
+    let mut broken_link_count = broken_links.len();
+    if args.external {
+        broken_link_count += check_external_links(config)?;
+    }
+
+    if broken_link_count == 0 {
+        println!("No broken links found");
+        Ok(())
+    } else {
+        println!("{} broken link(s) found", broken_link_count);
+        std::process::exit(1);
+    }
+}
+
+// Generate the site from the content, template and static directories into the build folder
+// The build carries on past errors (e.g. invalid frontmatter in one of the posts) so that all of
+// them are reported at once, but nothing is saved to the cache unless the build succeeded
+fn build_site(args: &cli::Args, config: Config) -> Result<(), Vec<BuildError>> {
+    let cache_context = retrieve_cache(&config);
+    let mut errors = Vec::new();
 
     let mut site = Site::new(config);
     info!(
@@ -334,127 +564,488 @@ fn main() -> std::io::Result<()> {
         site.configuration.build.minify_html, site.configuration.build.generate_sitemap
     );
 
-    let template_dir = &site.configuration.paths.template_dir;
-    let content_dir = &site.configuration.paths.content_dir;
-    let output_dir = &site.configuration.paths.output_dir;
-    let static_dir = &site.configuration.paths.static_dir;
+    let template_dir = &site.configuration.paths.template_dir.clone();
+    let content_dir = &site.configuration.paths.content_dir.clone();
+    let output_dir = &site.configuration.paths.output_dir.clone();
+    let static_dir = &site.configuration.paths.static_dir.clone();
 
-    let template_filepath = format!(
-        "{}/**/*.html",
-        template_dir
-            .to_str()
-            .expect("Template directory must be a UTF-8")
-    );
-    let tera_result = Tera::new(&template_filepath);
-    let tera = tera_result.unwrap();
+    // Processors of the pre stage run first, as they may generate files read by the rest of
+    // the build
+    let mut processor_cache =
+        ProcessorCache::load_or_default(output_dir.join("processors-cache.json"));
+    errors.extend(processors::run_stage(
+        &site.configuration.processors,
+        Stage::Pre,
+        &site.configuration.paths,
+        &mut processor_cache,
+        site.configuration.build.cache,
+    ));
+
+    // Pages can only be rendered once the templates are loaded, but the content is still read
+    // to report any other errors
+    let resize_image = resources::ResizeImage::new(static_dir, content_dir, output_dir);
+    let processed_images = resize_image.used_files();
+    let assets = AssetManifest::load_or_default(output_dir, &site.configuration.assets);
+    let tera = match load_templates(template_dir) {
+        Ok(mut tera) => {
+            tera.register_function("resize_image", resize_image);
+            tera.register_function("asset_url", assets.asset_url());
+            tera.register_function("asset_integrity", assets.asset_integrity());
+            Some(tera)
+        }
+        Err(e) => {
+            errors.push(e);
+            None
+        }
+    };
+
+    let static_rules = match resources::StaticRules::new(&site.configuration.static_files) {
+        Ok(static_rules) => static_rules,
+        Err(e) => {
+            errors.push(BuildError::Config {
+                path: site.configuration.paths.config_file.clone(),
+                line: None,
+                message: format!("invalid `static` pattern: {}", e),
+            });
+            return Err(errors);
+        }
+    };
 
     // Handles static resources (images, etc)
     // copy the static directory into the build folder.
     // These files do not require any extra processing by the SSG
     // but the following function runs an optimization for image files
     // Saves static files within a nested /static folder within the output directory
-    resources::optimize_and_copy_static_folder(
+    let minifier = Minifier::new(&site.configuration.build);
+    let static_cache_context = CacheContext::load_or_default(
+        output_dir.join("static-cache.json"),
+        &site.configuration.paths.root_dir,
+    );
+    // Files are copied again when the settings they are processed with change
+    let static_settings = serde_json::to_string(&(
+        site.configuration.build.minify_css,
+        site.configuration.build.minify_js,
+        &site.configuration.static_files,
+    ))
+    .unwrap_or_default();
+    let static_settings = blake3::hash(static_settings.as_bytes())
+        .to_hex()
+        .to_string();
+    if static_cache_context.update_settings(&static_settings) {
+        info!("Static file settings changed. Copying every static file");
+    }
+    if let Err(static_errors) = resources::optimize_and_copy_static_folder(
         Path::new(static_dir),
         Path::new(output_dir).join("static").as_path(),
-        &output_dir.join("static-cache.json"),
-    )?;
+        &static_cache_context,
+        &static_rules,
+        &minifier,
+    ) {
+        errors.extend(static_errors);
+    }
+
+    // Fingerprint the static files, along with the assets generated during the build (the
+    // stylesheet and the outputs of the processors within the static folder) as of the previous
+    // build. Those are fingerprinted again once generated
+    errors.extend(assets.add_static_folder(static_dir, &static_rules));
+    let generated_assets: Vec<PathBuf> = css::stylesheet_asset_path(&site.configuration.css)
+        .into_iter()
+        .chain(
+            site.configuration
+                .processors
+                .iter()
+                .filter_map(|processor| processor.output.as_deref()?.strip_prefix("static").ok()),
+        )
+        .map(Path::to_path_buf)
+        .collect();
+    for asset in &generated_assets {
+        if let Err(e) = assets.add(asset) {
+            errors.push(e);
+        }
+    }
 
     // Create output directory for the build results
-    fs::create_dir_all(output_dir)?;
+    if let Err(e) = fs::create_dir_all(output_dir) {
+        errors.push(BuildError::io(output_dir, e));
+        return Err(errors);
+    }
 
     // Pass 1: Create the Site struct representing the website based on recursively walking through
-    // the directories
+    // the directories. Markdown files are read in parallel, then added to the site in order
     // Note: files are added to the Site struct in descending order by filename (from the folder in which they are found)
-    let mut files: Vec<walkdir::DirEntry> = WalkDir::new(content_dir).into_iter().filter_map(Result::ok).collect();
+    let mut files: Vec<walkdir::DirEntry> = WalkDir::new(content_dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().extension().and_then(|e| e.to_str()) == Some("md"))
+        .collect();
     files.sort_by(|a, b| b.file_name().cmp(a.file_name()));
 
-    for entry in files {
-        let path = entry.path();
-        if path.extension().and_then(|e| e.to_str()) == Some("md") {
-            let path_buf = path.to_path_buf();
-
-            // Check if unmodified based on hash & modify metadata in cache
-            if site.configuration.build.cache {
-                if !cache_context.update_file_if_changed(&path_buf)? {
-                    // If the file was already in the cache, move onto the next file
-                    // without rebuilding the page
-                    // TODO: Need to add the page to the site when it is being retrieved 
-                    // from the cache so that other templates that pull in "page" and "post"
-                    // URLs/Titles still pulls in the entire site, and not just those that were
-                    // also rebuilt and not previously cached.
-                    // Site::add_page(&mut site, page, page_type);
-                    continue;
-                } else {
-                    info!("File {:?} was changed. Rebuilding", path_buf);
-                }
-            } else {
-                info!(
-                    "File {:?} was not cached. Cache on this build is disabled (check config.yml file)",
-                    path_buf
-                );
-            }
+    let settings_changed =
+        cache_context.update_settings(&render_settings_hash(&site.configuration));
+    if settings_changed && site.configuration.build.cache {
+        info!("Settings changed since the previous build. Rendering every page");
+    }
 
-            // Read markdown file
-            let markdown = fs::read_to_string(path)?;
+    let read_page = |path: &Path| -> Result<Option<Page>, BuildError> {
+        let bundle_dir = get_bundle_dir(path, content_dir);
 
-            // Retrieve the Markdown frontmatter & parse
-            let matter = Matter::<YAML>::new();
-            let parsed_frontmatter = matter.parse(&markdown);
+        // Read markdown file
+        let markdown = fs::read_to_string(path).map_err(|e| BuildError::io(path, e))?;
 
-            let html_template_file = get_template_name(path);
-            let page_type = match html_template_file.to_str().unwrap() {
-                "index" => PageType::Index,
-                "page" => PageType::Page,
-                "post" => PageType::Post,
-                _ => PageType::Unknown,
-            };
-            let page = if let Some(front) = parsed_frontmatter.data {
-                let frontmatter: Frontmatter = front.deserialize().unwrap();
-                Some(extract_page_info(
-                    site.configuration.metadata.base_url.clone(),
-                    path,
-                    frontmatter,
-                    parsed_frontmatter.content,
-                    page_type.clone(),
-                ))
-            } else {
-                None
+        // Retrieve the Markdown frontmatter & parse
+        let matter = Matter::<YAML>::new();
+        let parsed_frontmatter = matter.parse(&markdown);
+
+        // Within a page bundle, only the index.md file is a page, and the other markdown files
+        // are not copied as assets either, so those are warned about
+        if get_enclosing_bundle(path, content_dir).is_some() {
+            warn!(
+                "Ignoring {:?}: only the index.md file of a page bundle is a page",
+                path
+            );
+            return Ok(None);
+        }
+        let html_template_file = get_template_name(path, bundle_dir);
+        let page_type = match html_template_file.to_str() {
+            Some("index") => PageType::Index,
+            Some("page") => PageType::Page,
+            Some("post") => PageType::Post,
+            _ => PageType::Unknown,
+        };
+        // Files without a template are not rendered (nor cached), e.g. sections only used as
+        // data
+        if matches!(page_type, PageType::Unknown) {
+            info!(
+                "Ignoring {:?}: no `{}.html` template for its section",
+                path,
+                html_template_file.to_string_lossy()
+            );
+            return Ok(None);
+        }
+        let mut page = if parsed_frontmatter.data.is_some() {
+            // The frontmatter is deserialized with serde_yaml (rather than through gray_matter)
+            // for the location of any errors, offset by the lines before the frontmatter
+            let line_of = |text: &str| {
+                let offset = markdown.rfind(text).unwrap_or(0);
+                markdown[..offset].matches('\n').count() + 1
             };
+            let frontmatter: Frontmatter = serde_yaml::from_str(&parsed_frontmatter.matter)
+                .map_err(|e| {
+                    BuildError::frontmatter(path, line_of(&parsed_frontmatter.matter), &e)
+                })?;
+            if frontmatter.draft == Some(true) && !site.configuration.build.drafts {
+                // Skipped drafts are left out of the cache, so they are rendered as soon as
+                // drafts are enabled (and the output of a published page that became a draft is
+                // removed)
+                info!("Skipping draft {:?}", path);
+                return Ok(None);
+            }
+            let content_line = line_of(&parsed_frontmatter.content);
+            let mut page = extract_page_info(
+                site.configuration.metadata.base_url.clone(),
+                path,
+                frontmatter,
+                parsed_frontmatter.content,
+                page_type,
+                bundle_dir,
+                content_line,
+            );
+            page.reading_time =
+                parser::reading_time(page.word_count, site.configuration.content.words_per_minute);
+            Some(page)
+        } else {
+            None
+        };
+
+        // Check if unmodified based on hash & modify metadata in cache
+        // Unchanged pages are still added to the site (so that other templates that pull in
+        // "page" and "post" URLs/Titles still pull in the entire site), but are not rebuilt
+        // The file is checked even when the cache is disabled, to keep track of its outputs
+        let changed = cache_context.update_file_if_changed(path)?;
+        let rebuild = if site.configuration.build.cache {
+            if changed {
+                info!("File {:?} was changed. Rebuilding", path);
+            }
+            changed || settings_changed
+        } else {
+            info!(
+                "File {:?} was not cached. Cache on this build is disabled (check config.yml file)",
+                path
+            );
+            true
+        };
+        if let Some(page) = &mut page {
+            page.rebuild = rebuild;
+        }
 
-            if let Some(page) = page {
+        // Keep track of the generated HTML file, so it can be removed once the markdown file is
+        // deleted
+        let outputs: Vec<PathBuf> = page.iter().map(|page| site.get_output_path(page)).collect();
+        cache_context.record_outputs(path, &outputs);
+
+        Ok(page)
+    };
+
+    let pages: Vec<Result<Option<Page>, BuildError>> = files
+        .par_iter()
+        .map(|entry| read_page(entry.path()))
+        .collect();
+
+    for page in pages {
+        match page {
+            Ok(Some(page)) => {
                 // page metadata exists, add to the site data structure
+                let page_type = page.page_type.clone();
                 Site::add_page(&mut site, page, page_type);
             }
+            Ok(None) => {}
+            Err(e) => errors.push(e),
         }
     }
 
-    // Pass 2: Generate the HTML for each page in the site
-    // 1. Generate the index page
-    // 2. Generate the other pages
-    // 3. Generate the blog posts
-    // Initialize Tera for templating with the HTML files
+    // Copy/optimize the assets of page bundles next to the generated pages. This happens
+    // regardless of the markdown files changing, as the assets are cached separately. Only the
+    // bundles of the pages added to the site are copied (not those of skipped drafts)
+    errors.par_extend(
+        site.index
+            .iter()
+            .chain(&site.pages)
+            .chain(&site.posts)
+            .filter_map(|page| page.bundle.as_deref())
+            .collect::<Vec<&Path>>()
+            .into_par_iter()
+            .flat_map_iter(|bundle_dir| {
+                let bundle_name = bundle_dir.file_name().unwrap_or_default();
+                resources::optimize_and_copy_bundle_assets(
+                    bundle_dir,
+                    &output_dir.join(bundle_name),
+                    &static_cache_context,
+                    &static_rules,
+                    &minifier,
+                )
+                .err()
+                .unwrap_or_default()
+            }),
+    );
 
-    if let Some(index) = site.index.as_ref() {
-        if let Err(e) = site.generate_page(index, &tera) {
-            error!("Failed to generate index page '{}': {}", index.name, e);
+    // The content of every page is rendered once read, as the summaries appear within the
+    // listings of other pages (so they are needed even for the pages that are not rendered)
+    if let Some(tera) = &tera {
+        let summary_words = site.configuration.content.summary_words;
+        let mut pages: Vec<&mut Page> = site
+            .index
+            .iter_mut()
+            .chain(&mut site.pages)
+            .chain(&mut site.posts)
+            .collect();
+        errors.par_extend(pages.par_iter_mut().filter_map(|page| {
+            match render_markdown(page, tera, &static_rules, &assets) {
+                Ok(html) => {
+                    page.summary = page_summary(&html, summary_words);
+                    page.html = html;
+                    None
+                }
+                Err(e) => Some(e),
+            }
+        }));
+    }
+
+    let related_posts = related::related_posts(
+        &site.posts,
+        &site.configuration.related,
+        &site.configuration.search.stop_words,
+    );
+    // The related posts of a post depend on the other posts, so a post is rendered again when
+    // its list changed (e.g. a post was added, or the tags of another post changed)
+    for (post, related) in site.posts.iter_mut().zip(related_posts) {
+        let json = serde_json::to_string(&related).unwrap_or_default();
+        let hash = blake3::hash(json.as_bytes()).to_hex().to_string();
+        if cache_context.update_dependencies(&post.source, &hash) && !post.rebuild {
+            info!("Related posts of {:?} changed. Rebuilding", post.source);
+            post.rebuild = true;
         }
-    } else {
+        post.related = related;
+    }
+
+    // The index and the other pages list the posts (their titles, summaries, etc.), so they
+    // are rendered again whenever a post was added, removed or changed
+    let posts_json = serde_json::to_string(&site.posts).unwrap_or_default();
+    let posts_hash = blake3::hash(posts_json.as_bytes()).to_hex().to_string();
+    for page in site.index.iter_mut().chain(&mut site.pages) {
+        if cache_context.update_dependencies(&page.source, &posts_hash) && !page.rebuild {
+            info!("Posts changed. Rebuilding {:?}", page.source);
+            page.rebuild = true;
+        }
+    }
+
+    // Pass 2: Generate the HTML for each page in the site (index page, the other pages, and
+    // the blog posts) in parallel
+    if site.index.is_none() {
         error!("No index page found in site data");
     }
 
-    for page in &site.pages {
-        if let Err(e) = site.generate_page(page, &tera) {
-            error!("Failed to generate page '{}': {}", page.name, e);
+    let all_pages: Vec<&Page> = site
+        .index
+        .iter()
+        .chain(&site.pages)
+        .chain(&site.posts)
+        .collect();
+    // Unchanged pages still reference the assets of the previous build when any of them changed
+    let assets_changed = assets.changed();
+    if assets_changed {
+        info!("Assets changed since the previous build. Rendering every page");
+    }
+    let mut pages_to_render: Vec<&Page> = all_pages
+        .iter()
+        .copied()
+        .filter(|page| page.rebuild || assets_changed)
+        .collect();
+
+    let render = |pages: &[&Page]| -> Vec<BuildError> {
+        let Some(tera) = &tera else {
+            return Vec::new();
+        };
+        pages
+            .par_iter()
+            .filter_map(|page| {
+                site.generate_page(page, tera, &static_rules, &assets)
+                    .inspect_err(|e| {
+                        error!(
+                            "Failed to generate {:?} page '{}': {}",
+                            page.page_type, page.name, e
+                        )
+                    })
+                    .err()
+            })
+            .collect()
+    };
+    errors.extend(render(&pages_to_render));
+
+    let search_cache = match search::build_search_index(&site.configuration, &site.posts) {
+        Ok(search_cache) => search_cache,
+        Err(e) => {
+            errors.push(e);
+            None
+        }
+    };
+
+    // The stylesheet holds the classes used by every generated page, so it is built last
+    if let Err(e) = css::build_stylesheet(&site.configuration) {
+        errors.push(e);
+    }
+    errors.extend(processors::run_stage(
+        &site.configuration.processors,
+        Stage::Post,
+        &site.configuration.paths,
+        &mut processor_cache,
+        site.configuration.build.cache,
+    ));
+
+    // The generated assets do not depend on their URLs (or integrity digests) within the pages,
+    // so when any of them changed, the pages are rendered once more with the new ones
+    let mut generated_assets_changed = false;
+    for asset in &generated_assets {
+        match assets.add(asset) {
+            Ok(changed) => generated_assets_changed |= changed,
+            Err(e) => errors.push(e),
         }
     }
+    if generated_assets_changed && errors.is_empty() {
+        info!("Generated assets changed. Rendering every page with their new URLs");
+        pages_to_render = all_pages.clone();
+        errors.extend(render(&pages_to_render));
+    }
+
+    // Only save the caches once the build succeeded. Otherwise, pages that failed to generate
+    // would be marked as unchanged and skipped on the next build
+    if !errors.is_empty() {
+        error!("The build failed. The build cache was not saved");
+        return Err(errors);
+    }
+
+    // Remove the outputs left over from deleted/renamed source files. Processed images can only
+    // be pruned when every page was rendered (as the templates of unchanged pages did not
+    // request their images during this build)
+    let prune = || -> Result<Vec<PathBuf>, BuildError> {
+        let mut stale_outputs = cache_context.prune(args.dry_run)?;
+        stale_outputs.extend(static_cache_context.prune(args.dry_run)?);
+        stale_outputs.extend(assets.prune(args.dry_run)?);
+        stale_outputs.extend(search::prune(&site.configuration, args.dry_run)?);
+        if pages_to_render.len() == all_pages.len() {
+            stale_outputs.extend(resources::prune_processed_images(
+                output_dir,
+                &processed_images.lock().unwrap(),
+                args.dry_run,
+            )?);
+        }
+        Ok(stale_outputs)
+    };
+    let stale_outputs = prune().map_err(|e| vec![e])?;
+
+    // Compressed last, once every output is written
+    compress::compress_outputs(&site.configuration, args.dry_run)?;
 
-    for post in &site.posts {
-        if let Err(e) = site.generate_page(post, &tera) {
-            error!("Failed to generate post '{}': {}", post.name, e);
+    if args.dry_run {
+        println!(
+            "{} stale output file(s) would be removed",
+            stale_outputs.len()
+        );
+    } else {
+        info!("Removed {} stale output file(s)", stale_outputs.len());
+        cache_context.save().map_err(|e| vec![e])?;
+        static_cache_context.save().map_err(|e| vec![e])?;
+        processor_cache.save().map_err(|e| vec![e])?;
+        assets.save().map_err(|e| vec![e])?;
+        if let Some(search_cache) = &search_cache {
+            search::save_cache(&site.configuration, search_cache).map_err(|e| vec![e])?;
         }
     }
-    let _ = build_tailwind(&site);
+
+    minifier.print_summary(&site.configuration.paths.root_dir);
     info!("Static site generated in 'output/' directory!");
     debug!("Site generated: {:?}", site);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markdown_files_within_bundles_are_not_pages() {
+        let content_dir = std::env::temp_dir().join(format!("ssg-content-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&content_dir);
+        for path in [
+            "post/bundle/index.md",
+            "post/bundle/notes.md",
+            "post/bundle/drafts/extra.md",
+            "post/single.md",
+            "post/folder/readme.md",
+        ] {
+            let path = content_dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        let bundle = content_dir.join("post/bundle");
+        let enclosing_bundle = |path: &str| {
+            get_enclosing_bundle(&content_dir.join(path), &content_dir).map(Path::to_path_buf)
+        };
+
+        assert_eq!(
+            get_bundle_dir(&content_dir.join("post/bundle/index.md"), &content_dir),
+            Some(bundle.as_path())
+        );
+        assert_eq!(enclosing_bundle("post/bundle/index.md"), None);
+        assert_eq!(
+            enclosing_bundle("post/bundle/notes.md"),
+            Some(bundle.clone())
+        );
+        assert_eq!(
+            enclosing_bundle("post/bundle/drafts/extra.md"),
+            Some(bundle)
+        );
+        assert_eq!(enclosing_bundle("post/single.md"), None);
+        assert_eq!(enclosing_bundle("post/folder/readme.md"), None);
+        fs::remove_dir_all(&content_dir).unwrap();
+    }
+}
@@ -0,0 +1,174 @@
+// Minification of the standalone stylesheets and scripts copied from the static folder (and
+// page bundles). Files that fail to minify (e.g. a syntax the parser does not support) are
+// copied as-is with a warning. The bytes saved on every file are reported once the build is done
+use crate::config::Build;
+use crate::css;
+use log::warn;
+use minify_js::{Session, TopLevelMode};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// Size of a file before and after minification
+struct Savings {
+    path: PathBuf,
+    original: usize,
+    minified: usize,
+}
+
+pub struct Minifier {
+    css: bool,
+    js: bool,
+    savings: Mutex<Vec<Savings>>,
+}
+
+impl Minifier {
+    pub fn new(build: &Build) -> Self {
+        Minifier {
+            css: build.minify_css,
+            js: build.minify_js,
+            savings: Mutex::new(Vec::new()),
+        }
+    }
+
+    // Whether the file is a stylesheet (.css) or script (.js, .mjs) to minify
+    pub fn minifies(&self, path: &Path) -> bool {
+        match path.extension().and_then(OsStr::to_str) {
+            Some("css") => self.css,
+            Some("js" | "mjs") => self.js,
+            _ => false,
+        }
+    }
+
+    // Minified content of a stylesheet or script, or None when the file is left as-is (another
+    // kind of file, minification disabled or an error)
+    pub fn minify(&self, path: &Path, content: &[u8]) -> Option<Vec<u8>> {
+        if !self.minifies(path) {
+            return None;
+        }
+        let minified = match path.extension().and_then(OsStr::to_str) {
+            Some("css") => {
+                let css = String::from_utf8_lossy(content);
+                css::minify_css(&css, path).map(String::into_bytes)
+            }
+            Some("mjs") => minify_js(TopLevelMode::Module, content),
+            _ => minify_js(TopLevelMode::Global, content),
+        };
+        match minified {
+            Ok(minified) => {
+                self.savings.lock().unwrap().push(Savings {
+                    path: path.to_path_buf(),
+                    original: content.len(),
+                    minified: minified.len(),
+                });
+                Some(minified)
+            }
+            Err(e) => {
+                warn!("Could not minify {:?} (copying it as-is): {}", path, e);
+                None
+            }
+        }
+    }
+
+    // Report the files minified during the build, with the bytes saved on each of them
+    pub fn print_summary(&self, root: &Path) {
+        let mut savings = self.savings.lock().unwrap();
+        if savings.is_empty() {
+            return;
+        }
+        savings.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let original: usize = savings.iter().map(|savings| savings.original).sum();
+        let minified: usize = savings.iter().map(|savings| savings.minified).sum();
+        println!(
+            "Minified {} file(s), saving {} byte(s) ({})",
+            savings.len(),
+            original.saturating_sub(minified),
+            percentage(original, minified)
+        );
+        for savings in savings.iter() {
+            println!(
+                "  {}: {} -> {} byte(s) ({})",
+                savings
+                    .path
+                    .strip_prefix(root)
+                    .unwrap_or(&savings.path)
+                    .display(),
+                savings.original,
+                savings.minified,
+                percentage(savings.original, savings.minified)
+            );
+        }
+    }
+}
+
+fn minify_js(mode: TopLevelMode, content: &[u8]) -> Result<Vec<u8>, String> {
+    let session = Session::new();
+    let mut output = Vec::new();
+    minify_js::minify(&session, mode, content, &mut output).map_err(|e| format!("{:?}", e))?;
+    Ok(output)
+}
+
+// Change in size, e.g. "-32.5%"
+fn percentage(original: usize, minified: usize) -> String {
+    if original == 0 {
+        return "0.0%".to_string();
+    }
+    let change = (minified as f64 - original as f64) / original as f64 * 100.0;
+    format!("{:+.1}%", change)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn minifier(css: bool, js: bool) -> Minifier {
+        Minifier::new(&Build {
+            minify_css: css,
+            minify_js: js,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn only_enabled_stylesheets_and_scripts_are_minified() {
+        let minifier = minifier(true, false);
+        assert!(minifier.minifies(Path::new("styles/site.css")));
+        assert!(!minifier.minifies(Path::new("scripts/app.js")));
+        assert!(!minifier.minifies(Path::new("scripts/app.mjs")));
+        assert!(!minifier.minifies(Path::new("images/logo.svg")));
+        assert_eq!(minifier.minify(Path::new("app.js"), b"let a = 1;"), None);
+    }
+
+    #[test]
+    fn stylesheets_and_scripts_are_minified() {
+        let minifier = minifier(true, true);
+        let css = minifier
+            .minify(Path::new("site.css"), b"body {\n  color: #ff0000;\n}\n")
+            .unwrap();
+        assert_eq!(String::from_utf8(css).unwrap(), "body{color:red}");
+        let js = minifier
+            .minify(
+                Path::new("app.js"),
+                b"function greet(name) {\n  return 'Hello ' + name;\n}\ngreet('you');\n",
+            )
+            .unwrap();
+        let js = String::from_utf8(js).unwrap();
+        assert!(js.len() < 60 && js.contains("greet"), "{}", js);
+        assert_eq!(minifier.savings.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn invalid_files_are_left_as_is() {
+        let minifier = minifier(true, true);
+        assert_eq!(minifier.minify(Path::new("app.js"), b"function ( {"), None);
+        assert!(minifier.savings.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn percentage_of_the_change_in_size() {
+        assert_eq!(percentage(200, 135), "-32.5%");
+        assert_eq!(percentage(100, 100), "+0.0%");
+        assert_eq!(percentage(0, 0), "0.0%");
+    }
+}
@@ -1,27 +1,63 @@
+use crate::assets::AssetManifest;
+use crate::error::BuildError;
+use crate::resources::StaticRules;
 use pulldown_cmark::{html, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
 use std::fmt::Write;
+use std::path::Path;
 
-fn replace_file_extension(file_path: &str, new_extension: &str) -> String {
-    if let Some(dot_index) = file_path.rfind('.') {
-        // replace the extension after the last dot
-        format!("{}.{new_extension}", &file_path[..dot_index])
-    } else {
-        // if no extension exists append the new extension
-        format!("{file_path}.{new_extension}")
+// Page bundle a markdown file belongs to: the directory holding its assets and the folder the
+// assets are copied to (relative to the generated page)
+pub struct Bundle<'a> {
+    pub dir: &'a Path,
+    pub url: &'a str,
+}
+
+impl Bundle<'_> {
+    // A relative URL resolves to the bundle's assets when the file exists in the bundle
+    // directory. Otherwise, it is left to resolve as usual (e.g. to the static folder)
+    fn contains(&self, url: &str) -> bool {
+        let is_relative =
+            !(url.is_empty() || url.starts_with('/') || url.starts_with('#') || url.contains(':'));
+        is_relative && self.dir.join(url).is_file()
+    }
+
+    // URL of one of the bundle's assets, as copied next to the page (e.g. with the extension of
+    // an optimized image)
+    fn asset_url(&self, static_rules: &StaticRules, url: &str) -> String {
+        format!(
+            "./{}/{}",
+            self.url,
+            static_rules.output_path_for(Path::new(url)).display()
+        )
     }
 }
 
 // TODO: Fix bugs around the parsing function (ALT tag on img specifically)
 // and clean up code
-pub fn parse_markdown_with_tailwind(md_content: &str, tera: &tera::Tera) -> String {
-    let parser = Parser::new_ext(md_content, Options::all());
+//
+// For page bundles, relative image and link URLs to files within the bundle resolve to the
+// bundle's copied assets instead of the static folder. Images of the static folder resolve to
+// their fingerprinted copies (see the asset manifest)
+//
+// Errors are reported against the source markdown file. first_line is the line of the file the
+// content starts on (i.e. after the frontmatter)
+pub fn parse_markdown_with_tailwind(
+    md_content: &str,
+    tera: &tera::Tera,
+    static_rules: &StaticRules,
+    assets: &AssetManifest,
+    bundle: Option<&Bundle>,
+    source: &Path,
+    first_line: usize,
+) -> Result<String, BuildError> {
+    let parser = Parser::new_ext(md_content, Options::all()).into_offset_iter();
     let mut html_output = String::new();
 
     let mut is_inside_header = false;
     let mut is_inside_image = false;
     let mut image_alt_text = String::new();
 
-    for event in parser {
+    for (event, range) in parser {
         match event {
             // Customize headers
             Event::Start(Tag::Heading {
@@ -31,7 +67,11 @@ pub fn parse_markdown_with_tailwind(md_content: &str, tera: &tera::Tera) -> Stri
                 attrs: _,
             }) => {
                 if is_inside_header {
-                    panic!("Nested headers are not allowed");
+                    return Err(BuildError::Markdown {
+                        path: source.to_path_buf(),
+                        line: first_line + md_content[..range.start].matches('\n').count(),
+                        message: "nested headers are not allowed".to_string(),
+                    });
                 }
                 is_inside_header = true;
                 let class = match level {
@@ -71,25 +111,30 @@ pub fn parse_markdown_with_tailwind(md_content: &str, tera: &tera::Tera) -> Stri
                     let mut context = std::collections::HashMap::new();
                     // Match destination to the static folder within the build directory
                     // within the content markdown files DO NOT INCLUDE PATH TO static directory
-                    // Just the sub-directory is needed
+                    // Just the sub-directory is needed. Relative paths in page bundles match
+                    // the bundle's asset folder instead
                     //
-                    // Replace the file extension with webp (the file format that all images)
-                    // turns into during the optimization/copy stages of the static folder
-                    context.insert(
-                        "src",
-                        format!("./static/{}", replace_file_extension(&dest_url, &"webp")),
-                    );
+                    // Replace the file extension with the one the image is given during the
+                    // optimization/copy stages of the static folder (webp for optimized images),
+                    // and the name of its fingerprinted copy
+                    let path = Path::new(dest_url.as_ref());
+                    let src = match bundle {
+                        Some(bundle) if bundle.contains(&dest_url) => {
+                            bundle.asset_url(static_rules, &dest_url)
+                        }
+                        _ => format!("./static/{}", assets.resolve(static_rules, path)),
+                    };
+                    context.insert("src", src);
                     context.insert("alt", title.to_string());
                     context
                 })
                 .unwrap();
 
                 // Render image using the partial template that was previously defined
-                html_output.push_str(
-                    &tera
-                        .render("partials/image.html", &image_data)
-                        .unwrap_or_else(|_| "<!-- Failed to render image -->".to_string()),
-                );
+                let image_html = tera
+                    .render("partials/image.html", &image_data)
+                    .map_err(|e| BuildError::template(Some(source), &e))?;
+                html_output.push_str(&image_html);
             }
 
             Event::End(TagEnd::Image) => {
@@ -102,9 +147,16 @@ pub fn parse_markdown_with_tailwind(md_content: &str, tera: &tera::Tera) -> Stri
                 title,
                 id: _,
             }) => {
+                // Links to the bundle's images point to their optimized copies, like images do
+                let dest_url = match bundle {
+                    Some(bundle) if bundle.contains(&dest_url) => {
+                        bundle.asset_url(static_rules, &dest_url)
+                    }
+                    _ => dest_url.to_string(),
+                };
                 html_output.push_str(&format!(
                     "<a class=\"text-base font-bold leading-relaxed text-green-700\" href=\"{}\" title=\"{}\">",
-                    dest_url.to_string(), title.to_string(),
+                    dest_url, title,
                 ));
             }
 
@@ -159,5 +211,404 @@ pub fn parse_markdown_with_tailwind(md_content: &str, tera: &tera::Tera) -> Stri
         }
     }
 
-    html_output
+    Ok(html_output)
+}
+
+// Collect the outbound (http/https) URLs of every link within the markdown content
+pub fn collect_external_links(md_content: &str) -> Vec<String> {
+    Parser::new_ext(md_content, Options::all())
+        .filter_map(|event| match event {
+            Event::Start(Tag::Link { dest_url, .. })
+                if dest_url.starts_with("http://") || dest_url.starts_with("https://") =>
+            {
+                Some(dest_url.to_string())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+// Number of words within the markdown content, leaving out the code blocks (and the frontmatter,
+// which is not part of the content)
+pub fn word_count(md_content: &str) -> usize {
+    plain_text(md_content)
+        .split_whitespace()
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .count()
+}
+
+// Minutes it takes to read the given number of words, rounded up (at least a minute for any
+// content)
+pub fn reading_time(word_count: usize, words_per_minute: u32) -> usize {
+    word_count.div_ceil(words_per_minute.max(1) as usize)
+}
+
+// Plain text of the markdown content, without the code blocks
+pub fn plain_text(md_content: &str) -> String {
+    let mut html = String::new();
+    html::push_html(&mut html, Parser::new_ext(md_content, Options::all()));
+    html_text(&html, &CODE_ELEMENTS)
+}
+
+// Elements whose text is not part of the content as read (code blocks, scripts & styles)
+const CODE_ELEMENTS: [&str; 3] = ["pre", "script", "style"];
+
+// Elements whose text is separated from the surrounding text
+const BLOCK_ELEMENTS: [&str; 18] = [
+    "p",
+    "br",
+    "div",
+    "li",
+    "ul",
+    "ol",
+    "blockquote",
+    "table",
+    "tr",
+    "td",
+    "th",
+    "hr",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+];
+
+// Plain text of the HTML, without the content of the skipped elements. Character references
+// are decoded, and whitespace is collapsed
+pub fn html_text(html: &str, skipped_elements: &[&str]) -> String {
+    let mut text = String::new();
+    let mut skipped_element: Option<String> = None;
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        if skipped_element.is_none() {
+            text.push_str(&rest[..start]);
+        }
+        let end = rest[start..]
+            .find('>')
+            .map_or(rest.len(), |end| start + end + 1);
+        let tag = rest[start + 1..end].trim_end_matches('>');
+        let tag_name: String = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+
+        match &skipped_element {
+            Some(element) if tag.starts_with('/') && *element == tag_name => {
+                skipped_element = None;
+            }
+            None if !tag.starts_with('/') && skipped_elements.contains(&tag_name.as_str()) => {
+                skipped_element = Some(tag_name.clone());
+            }
+            _ => {}
+        }
+        // block elements separate words (e.g. list items)
+        if BLOCK_ELEMENTS.contains(&tag_name.as_str()) {
+            text.push(' ');
+        }
+        rest = &rest[end..];
+    }
+    if skipped_element.is_none() {
+        text.push_str(rest);
+    }
+
+    let text = decode_entities(&text);
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// Decode the character references of HTML text or attribute values: the named references
+// escaped by the generated HTML, and numeric ones (e.g. `&#39;` or `&#x2F;`). Anything else is
+// left as-is
+pub fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        // references are short, so a distant semicolon is not part of one
+        let reference = rest[1..]
+            .find(';')
+            .filter(|end| *end <= 32)
+            .map(|end| &rest[1..end + 1]);
+        let character = reference.and_then(|reference| match reference {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            _ => {
+                let number = reference.strip_prefix('#')?;
+                let code = match number.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => number.parse().ok()?,
+                };
+                char::from_u32(code)
+            }
+        });
+        match (reference, character) {
+            (Some(reference), Some(character)) => {
+                decoded.push(character);
+                rest = &rest[reference.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+// Marker separating the summary of a page from the rest of its content
+const MORE_MARKER: &str = "<!-- more -->";
+
+// Elements without a closing tag
+const VOID_ELEMENTS: [&str; 12] = [
+    "area", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track", "wbr",
+];
+
+// The content (markdown, or the HTML it is rendered to) before the `<!-- more -->` marker, if
+// there is one
+pub fn content_before_more(md_content: &str) -> Option<&str> {
+    md_content
+        .find(MORE_MARKER)
+        .map(|offset| &md_content[..offset])
+}
+
+// The first words of the HTML (up to max_words, followed by an ellipsis when the HTML is cut
+// short). The elements left open at the cut (or at the end of the HTML) are closed, so the
+// result is balanced, and the elements that would be left empty (opened right before the cut)
+// are dropped
+pub fn truncate_html(html: &str, max_words: usize) -> String {
+    let mut output = String::new();
+    // name, and the offsets of the element's start tag & content within the output
+    let mut open_elements: Vec<(String, usize, usize)> = Vec::new();
+    let mut words = 0;
+    let mut rest = html;
+
+    loop {
+        let text_end = rest.find('<').unwrap_or(rest.len());
+        let text = &rest[..text_end];
+        let mut is_inside_word = false;
+        for (offset, c) in text.char_indices() {
+            if c.is_whitespace() {
+                is_inside_word = false;
+            } else if !is_inside_word {
+                is_inside_word = true;
+                words += 1;
+                if words > max_words {
+                    output.push_str(&text[..offset]);
+                    // trailing whitespace is trimmed before checking for empty elements (and
+                    // again once one is dropped, e.g. the newline before a dropped paragraph)
+                    loop {
+                        output.truncate(output.trim_end().len());
+                        match open_elements.last() {
+                            Some((_, start, content_start)) if output.len() == *content_start => {
+                                output.truncate(*start);
+                                open_elements.pop();
+                            }
+                            _ => break,
+                        }
+                    }
+                    // the ellipsis goes after the last word (rather than after closing tags)
+                    let mut position = output.len();
+                    while output[..position].ends_with('>') {
+                        match output[..position].rfind('<') {
+                            Some(tag_start) if output[tag_start..].starts_with("</") => {
+                                position = tag_start
+                            }
+                            _ => break,
+                        }
+                    }
+                    output.insert(position, '…');
+                    for (element, _, _) in open_elements.iter().rev() {
+                        write!(output, "</{}>", element).unwrap();
+                    }
+                    return output;
+                }
+            }
+        }
+        output.push_str(text);
+        if text_end == rest.len() {
+            for (element, _, _) in open_elements.iter().rev() {
+                write!(output, "</{}>", element).unwrap();
+            }
+            return output;
+        }
+
+        let tag_end = rest[text_end..]
+            .find('>')
+            .map_or(rest.len(), |end| text_end + end + 1);
+        let tag = &rest[text_end..tag_end];
+        let name: String = tag
+            .trim_start_matches(['<', '/'])
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+        let start = output.len();
+        output.push_str(tag);
+        if tag.starts_with("</") {
+            if let Some(index) = open_elements
+                .iter()
+                .rposition(|(element, _, _)| *element == name)
+            {
+                open_elements.truncate(index);
+            }
+        } else if !name.is_empty()
+            && !tag.ends_with("/>")
+            && !VOID_ELEMENTS.contains(&name.as_str())
+        {
+            open_elements.push((name, start, output.len()));
+        }
+        rest = &rest[tag_end..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn truncate_html_keeps_short_html() {
+        assert_eq!(truncate_html("<p>two words</p>", 2), "<p>two words</p>");
+    }
+
+    #[test]
+    fn truncate_html_closes_open_elements() {
+        assert_eq!(
+            truncate_html("<p>one <strong>two three</strong> four</p>", 2),
+            "<p>one <strong>two…</strong></p>"
+        );
+    }
+
+    #[test]
+    fn truncate_html_ignores_void_elements() {
+        assert_eq!(
+            truncate_html("<p>one<br>two <img src=\"a.png\"/> three</p>", 2),
+            "<p>one<br>two <img src=\"a.png\"/>…</p>"
+        );
+    }
+
+    #[test]
+    fn truncate_html_drops_elements_left_empty() {
+        assert_eq!(
+            truncate_html("<p>one two</p>\n<p><em>three</em></p>", 2),
+            "<p>one two…</p>"
+        );
+    }
+
+    #[test]
+    fn truncate_html_drops_nested_elements_left_empty() {
+        assert_eq!(
+            truncate_html(
+                "<p>one two</p>\n<blockquote>\n<p>three</p>\n</blockquote>",
+                2
+            ),
+            "<p>one two…</p>"
+        );
+    }
+
+    #[test]
+    fn truncate_html_closes_elements_left_open_at_the_end() {
+        assert_eq!(
+            truncate_html("<ul>\n<li>one</li>", usize::MAX),
+            "<ul>\n<li>one</li></ul>"
+        );
+    }
+
+    #[test]
+    fn decodes_entities() {
+        assert_eq!(
+            decode_entities("a &amp;lt; b &lt; c &#39;d&#x2F;&#X2f; &quot;e&quot;&nbsp;"),
+            "a &lt; b < c 'd// \"e\"\u{a0}"
+        );
+        assert_eq!(
+            decode_entities("AT&T & &unknown; &#xZZ;"),
+            "AT&T & &unknown; &#xZZ;"
+        );
+    }
+
+    #[test]
+    fn html_text_skips_elements_and_separates_blocks() {
+        let html = "<h1>Title</h1><p>One&amp;two</p><ul><li>three</li><li>four</li></ul><pre><code>let x;</code></pre><p>five <code>six</code></p>";
+        assert_eq!(
+            html_text(html, &CODE_ELEMENTS),
+            "Title One&two three four five six"
+        );
+        assert_eq!(
+            html_text(html, &["h1", "pre"]),
+            "One&two three four five six"
+        );
+    }
+
+    #[test]
+    fn plain_text_leaves_out_code_blocks() {
+        let markdown =
+            "# Title\n\nSome *text* & `code`.\n\n```rust\nfn main() {}\n```\n\n- one\n- two\n";
+        assert_eq!(plain_text(markdown), "Title Some text & code. one two");
+        assert_eq!(word_count(markdown), 6);
+    }
+
+    #[test]
+    fn word_count_skips_punctuation_and_markup() {
+        assert_eq!(word_count(""), 0);
+        assert_eq!(
+            word_count("A [link](https://example.com) -- and **bold** text!\n\n> quote\n\n---"),
+            6
+        );
+        assert_eq!(word_count("<div class=\"note\">inline html</div>"), 2);
+    }
+
+    #[test]
+    fn reading_time_rounds_up_to_whole_minutes() {
+        assert_eq!(reading_time(0, 200), 0);
+        assert_eq!(reading_time(1, 200), 1);
+        assert_eq!(reading_time(200, 200), 1);
+        assert_eq!(reading_time(201, 200), 2);
+        assert_eq!(reading_time(10, 0), 10);
+    }
+
+    #[test]
+    fn bundle_assets_resolve_to_their_copies() {
+        let dir = std::env::temp_dir().join(format!("ssg-bundle-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("pic.png"), "").unwrap();
+        fs::write(dir.join("notes.pdf"), "").unwrap();
+        let bundle = Bundle {
+            dir: &dir,
+            url: "my-post",
+        };
+        let static_rules = StaticRules::new(&Default::default()).unwrap();
+
+        assert!(bundle.contains("pic.png"));
+        assert!(!bundle.contains("missing.png"));
+        assert!(!bundle.contains("/pic.png"));
+        assert!(!bundle.contains("https://example.com/pic.png"));
+        assert_eq!(
+            bundle.asset_url(&static_rules, "pic.png"),
+            "./my-post/pic.webp"
+        );
+        assert_eq!(
+            bundle.asset_url(&static_rules, "notes.pdf"),
+            "./my-post/notes.pdf"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn content_before_more_splits_at_the_marker() {
+        assert_eq!(
+            content_before_more("summary\n<!-- more -->\nrest"),
+            Some("summary\n")
+        );
+        assert_eq!(content_before_more("no marker"), None);
+    }
 }
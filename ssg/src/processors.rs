@@ -0,0 +1,430 @@
+// External commands run as stages of the build (the `processors` section of the configuration
+// YAML), e.g. a JavaScript bundler or another CSS tool. Their output is captured and reported
+// along with the build, and a processor is skipped when its inputs (and its command) did not
+// change since it last succeeded
+use crate::cache;
+use crate::config::Paths;
+use crate::error::BuildError;
+use globset::{GlobBuilder, GlobMatcher};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use walkdir::WalkDir;
+
+// Version of the processor cache file format
+const PROCESSOR_CACHE_VERSION: u32 = 1;
+
+// Stage of the build a processor runs in
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    // Before the content is read and the templates are loaded (e.g. to generate data files)
+    Pre,
+    // Once the pages and the stylesheet are generated (e.g. to bundle scripts)
+    #[default]
+    Post,
+}
+
+// A processor from the configuration YAML file:
+// - command: the program and its arguments (not run through a shell). `{output}` and
+//   `{output_dir}` are replaced with the absolute output path and build folder
+// - inputs: globs relative to the project root. The processor only runs again once one of the
+//   matching files changed (it always runs without inputs)
+// - output: file generated by the processor, relative to the build folder
+// - working_dir: directory the command runs in, relative to the project root
+// - fail_build: whether a failure fails the build, or is only reported as a warning
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Processor {
+    pub name: String,
+    pub command: Vec<String>,
+    #[serde(default)]
+    pub inputs: Vec<String>,
+    #[serde(default)]
+    pub output: Option<PathBuf>,
+    #[serde(default)]
+    pub working_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub stage: Stage,
+    #[serde(default = "default_fail_build")]
+    pub fail_build: bool,
+}
+
+fn default_fail_build() -> bool {
+    true
+}
+
+impl Processor {
+    // Compiled input globs, along with the directory to search for matching files (the part of
+    // the glob before any wildcard)
+    pub fn input_matchers(&self, root: &Path) -> Result<Vec<(PathBuf, GlobMatcher)>, String> {
+        self.inputs
+            .iter()
+            .map(|pattern| {
+                let matcher = GlobBuilder::new(pattern)
+                    .literal_separator(true)
+                    .build()
+                    .map_err(|e| format!("processor `{}`: {}", self.name, e))?
+                    .compile_matcher();
+                let base: PathBuf = Path::new(pattern)
+                    .components()
+                    .take_while(|component| {
+                        !component
+                            .as_os_str()
+                            .to_string_lossy()
+                            .contains(['*', '?', '[', '{'])
+                    })
+                    .collect();
+                Ok((root.join(base), matcher))
+            })
+            .collect()
+    }
+
+    // Files matching the input globs, sorted
+    pub fn input_files(&self, root: &Path, output_dir: &Path) -> Result<Vec<PathBuf>, String> {
+        let mut files = Vec::new();
+        for (base, matcher) in self.input_matchers(root)? {
+            files.extend(
+                WalkDir::new(&base)
+                    .into_iter()
+                    .filter_map(Result::ok)
+                    .filter(|entry| entry.file_type().is_file())
+                    .map(|entry| entry.into_path())
+                    // outputs within the build folder would always be out of date
+                    .filter(|path| !path.starts_with(output_dir))
+                    .filter(|path| {
+                        path.strip_prefix(root)
+                            .is_ok_and(|relative| matcher.is_match(relative))
+                    }),
+            );
+        }
+        files.sort();
+        files.dedup();
+        Ok(files)
+    }
+}
+
+// Hashes of the inputs each processor last succeeded with, saved in the build folder
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ProcessorHashes {
+    version: u32,
+    hashes: BTreeMap<String, String>,
+}
+
+pub struct ProcessorCache {
+    path: PathBuf,
+    hashes: BTreeMap<String, String>,
+    dirty: bool,
+}
+
+impl ProcessorCache {
+    pub fn load_or_default(path: PathBuf) -> Self {
+        let hashes = match fs::read_to_string(&path) {
+            Ok(data) => match serde_json::from_str::<ProcessorHashes>(&data) {
+                Ok(cache) if cache.version == PROCESSOR_CACHE_VERSION => cache.hashes,
+                _ => {
+                    warn!("Discarding unreadable processor cache {:?}", path);
+                    BTreeMap::new()
+                }
+            },
+            Err(_) => BTreeMap::new(),
+        };
+        ProcessorCache {
+            path,
+            hashes,
+            dirty: false,
+        }
+    }
+
+    // Write the cache to the disk if a processor ran during the build
+    pub fn save(&self) -> Result<(), BuildError> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let cache = ProcessorHashes {
+            version: PROCESSOR_CACHE_VERSION,
+            hashes: self.hashes.clone(),
+        };
+        let json = serde_json::to_string(&cache).map_err(std::io::Error::other);
+        json.and_then(|json| cache::write_file_atomic(&self.path, json.as_bytes()))
+            .map_err(|e| BuildError::io(&self.path, e))
+    }
+}
+
+// Hash of everything a processor's output depends on: its command, working directory and
+// output, along with the path & content of every input file
+fn input_hash(
+    processor: &Processor,
+    inputs: &[PathBuf],
+    root: &Path,
+) -> Result<String, BuildError> {
+    let mut hasher = blake3::Hasher::new();
+    for arg in &processor.command {
+        hasher.update(arg.as_bytes());
+        hasher.update(b"\0");
+    }
+    hasher.update(format!("{:?}\0{:?}\0", processor.working_dir, processor.output).as_bytes());
+    for input in inputs {
+        let hash = cache::compute_file_hash(input).map_err(|e| BuildError::io(input, e))?;
+        let relative = input.strip_prefix(root).unwrap_or(input);
+        hasher.update(format!("{}\0{}\0", relative.display(), hash).as_bytes());
+    }
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+// Log the captured output of a processor, line by line
+fn log_output(name: &str, stream: &str, output: &[u8]) {
+    for line in String::from_utf8_lossy(output).lines() {
+        info!("[{}] {}: {}", name, stream, line);
+    }
+}
+
+// Run the processors of a stage in the order they are declared (so that a processor can use
+// the output of a previous one). Processors with unchanged inputs are skipped when the cache is
+// enabled. Returns the errors of the processors that fail the build
+pub fn run_stage(
+    processors: &[Processor],
+    stage: Stage,
+    paths: &Paths,
+    processor_cache: &mut ProcessorCache,
+    use_cache: bool,
+) -> Vec<BuildError> {
+    let mut errors = Vec::new();
+    for processor in processors
+        .iter()
+        .filter(|processor| processor.stage == stage)
+    {
+        match run_processor(processor, paths, processor_cache, use_cache) {
+            Ok(()) => {}
+            Err(e) if processor.fail_build => errors.push(e),
+            Err(e) => warn!("{} (ignored, as `fail_build` is false)", e),
+        }
+    }
+    errors
+}
+
+fn run_processor(
+    processor: &Processor,
+    paths: &Paths,
+    processor_cache: &mut ProcessorCache,
+    use_cache: bool,
+) -> Result<(), BuildError> {
+    let error = |message: String| BuildError::Processor {
+        name: processor.name.clone(),
+        message,
+    };
+    let root = &paths.root_dir;
+    let output = processor
+        .output
+        .as_ref()
+        .map(|output| paths.output_dir.join(output));
+
+    let inputs = processor
+        .input_files(root, &paths.output_dir)
+        .map_err(error)?;
+    let hash = input_hash(processor, &inputs, root)?;
+    let output_exists = output.as_ref().is_none_or(|output| output.exists());
+    if use_cache
+        && !processor.inputs.is_empty()
+        && output_exists
+        && processor_cache.hashes.get(&processor.name) == Some(&hash)
+    {
+        info!("Processor `{}` is up to date", processor.name);
+        return Ok(());
+    }
+
+    let (program, args) = processor
+        .command
+        .split_first()
+        .ok_or_else(|| error("`command` is empty".to_string()))?;
+    // The command runs within its working directory, so the paths it is given are absolute
+    // (the build folder is relative to the current directory when the configuration path is)
+    let absolute = |path: &Path| std::path::absolute(path).map_err(|e| BuildError::io(path, e));
+    let output_dir = absolute(&paths.output_dir)?;
+    let output_path = absolute(output.as_deref().unwrap_or(&paths.output_dir))?;
+    let args: Vec<String> = args
+        .iter()
+        .map(|arg| {
+            arg.replace("{output}", &output_path.to_string_lossy())
+                .replace("{output_dir}", &output_dir.to_string_lossy())
+        })
+        .collect();
+    if let Some(parent) = output.as_ref().and_then(|output| output.parent()) {
+        fs::create_dir_all(parent).map_err(|e| BuildError::io(parent, e))?;
+    }
+
+    let working_dir = root.join(processor.working_dir.as_deref().unwrap_or(Path::new("")));
+    info!(
+        "Running processor `{}` ({} input file(s)): {} {}",
+        processor.name,
+        inputs.len(),
+        program,
+        args.join(" ")
+    );
+    debug!("Working directory: {:?}", working_dir);
+    let result = Command::new(program)
+        .args(&args)
+        .current_dir(&working_dir)
+        .output()
+        .map_err(|e| error(format!("failed to run `{}`: {}", program, e)))?;
+
+    log_output(&processor.name, "stdout", &result.stdout);
+    log_output(&processor.name, "stderr", &result.stderr);
+    if !result.status.success() {
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        let mut message = format!("`{}` exited with {}", program, result.status);
+        if !stderr.trim().is_empty() {
+            message = format!("{}: {}", message, stderr.trim());
+        }
+        return Err(error(message));
+    }
+    if let Some(output) = output.filter(|output| !output.exists()) {
+        return Err(error(format!("output {:?} was not generated", output)));
+    }
+
+    processor_cache.hashes.insert(processor.name.clone(), hash);
+    processor_cache.dirty = true;
+    Ok(())
+}
+
+// Check the processors of the configuration: unique names, a command and valid input globs
+pub fn validate(processors: &[Processor], root: &Path) -> Vec<String> {
+    let mut errors = Vec::new();
+    for (index, processor) in processors.iter().enumerate() {
+        if processors[..index]
+            .iter()
+            .any(|other| other.name == processor.name)
+        {
+            errors.push(format!("duplicate processor name `{}`", processor.name));
+        }
+        if processor.command.is_empty() {
+            errors.push(format!(
+                "processor `{}`: `command` is empty",
+                processor.name
+            ));
+        }
+        if let Err(e) = processor.input_matchers(root) {
+            errors.push(e);
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn processor(yaml: &str) -> Processor {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    // Project root within the temporary directory, holding the given (empty) files
+    fn temp_root(name: &str, files: &[&str]) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("ssg-processors-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        for file in files {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, file).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn input_files_match_the_globs_outside_the_build_folder() {
+        let root = temp_root(
+            "inputs",
+            &["src/a.js", "src/nested/b.js", "src/c.css", "build/out.js"],
+        );
+        let files = |inputs: &str| {
+            processor(&format!(
+                "{{name: js, command: [esbuild], inputs: {}}}",
+                inputs
+            ))
+            .input_files(&root, &root.join("build"))
+            .unwrap()
+        };
+        assert_eq!(files("['src/*.js']"), [root.join("src/a.js")]);
+        assert_eq!(
+            files("['**/*.js', 'src/a.js']"),
+            [root.join("src/a.js"), root.join("src/nested/b.js")]
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn validate_reports_every_problem() {
+        let processors = [
+            processor("{name: js, command: [esbuild]}"),
+            processor("{name: js, command: []}"),
+            processor("{name: css, command: [postcss], inputs: ['src/[a']}"),
+        ];
+        let errors = validate(&processors, Path::new("."));
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+        assert_eq!(errors[0], "duplicate processor name `js`");
+        assert_eq!(errors[1], "processor `js`: `command` is empty");
+        assert!(errors[2].starts_with("processor `css`: "));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn processors_run_again_only_once_their_inputs_change() {
+        let root = temp_root("run", &["src/a.js"]);
+        let paths = Paths {
+            root_dir: root.clone(),
+            output_dir: root.join("build"),
+            ..Default::default()
+        };
+        // The output path is given to the command as an absolute path
+        let processors = [processor(
+            "{name: copy, command: [sh, -c, 'cat src/a.js > \"$0\"', '{output}'], \
+             inputs: ['src/*.js'], output: js/out.js}",
+        )];
+        let output = root.join("build/js/out.js");
+        let mut cache = ProcessorCache::load_or_default(root.join("build/processor-cache.json"));
+        let mut run = || run_stage(&processors, Stage::Post, &paths, &mut cache, true);
+
+        assert!(run().is_empty());
+        assert_eq!(fs::read_to_string(&output).unwrap(), "src/a.js");
+        fs::write(&output, "edited").unwrap();
+        assert!(run().is_empty());
+        assert_eq!(fs::read_to_string(&output).unwrap(), "edited");
+        fs::write(root.join("src/a.js"), "changed").unwrap();
+        assert!(run().is_empty());
+        assert_eq!(fs::read_to_string(&output).unwrap(), "changed");
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failures_fail_the_build_unless_disabled() {
+        let root = temp_root("failures", &[]);
+        let paths = Paths {
+            root_dir: root.clone(),
+            output_dir: root.join("build"),
+            ..Default::default()
+        };
+        let processors = [
+            processor("{name: required, command: ['false']}"),
+            processor("{name: optional, command: ['false'], fail_build: false}"),
+            processor("{name: missing, command: ['true'], output: missing.js}"),
+            processor("{name: pre, command: ['false'], stage: pre}"),
+        ];
+        let mut cache = ProcessorCache::load_or_default(root.join("build/processor-cache.json"));
+        let errors = run_stage(&processors, Stage::Post, &paths, &mut cache, true);
+        let names: Vec<&str> = errors
+            .iter()
+            .map(|error| match error {
+                BuildError::Processor { name, .. } => name.as_str(),
+                _ => panic!("unexpected error {}", error),
+            })
+            .collect();
+        assert_eq!(names, ["required", "missing"]);
+        assert!(errors[1].to_string().contains("was not generated"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,210 @@
+// Related posts, listed at the bottom of every post (`page.related`). Other posts are scored on
+// the tags they share with the post and, optionally, on the overlap of the terms of their
+// content
+use crate::parser;
+use crate::search;
+use crate::Page;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+// Related posts settings from the configuration YAML (the `related` section)
+// - limit: maximum number of related posts of a post
+// - content_similarity: also score posts on the terms their content has in common (the share
+//   of the terms of both posts that appear in each, leaving out the search stop words). Posts
+//   with no tags in common can then be related, and ties between posts are broken
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RelatedConfig {
+    pub limit: usize,
+    pub content_similarity: bool,
+}
+
+impl Default for RelatedConfig {
+    fn default() -> Self {
+        RelatedConfig {
+            limit: 3,
+            content_similarity: false,
+        }
+    }
+}
+
+// A related post, as exposed to the templates
+#[derive(Debug, Clone, Serialize)]
+pub struct RelatedPost {
+    pub title: Option<String>,
+    pub url: Option<String>,
+    pub date: Option<String>,
+}
+
+// Jaccard similarity of two sets of terms (0 when either is empty)
+fn similarity(a: &HashSet<String>, b: &HashSet<String>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+// Related posts of every post (in the same order). A post scores one point for every tag it
+// shares with the post, plus its content similarity (between 0 and 1) when enabled. Posts with
+// a score of 0 are left out, and posts with the same score are ordered by date (newest first),
+// then by name, so the lists do not depend on the order the posts were read in
+pub fn related_posts(
+    posts: &[Page],
+    config: &RelatedConfig,
+    stop_words: &[String],
+) -> Vec<Vec<RelatedPost>> {
+    let tags: Vec<HashSet<&str>> = posts
+        .iter()
+        .map(|post| post.tags.iter().flatten().map(String::as_str).collect())
+        .collect();
+    let stop_words: HashSet<&str> = stop_words.iter().map(String::as_str).collect();
+    let terms: Vec<HashSet<String>> = if config.content_similarity {
+        posts
+            .iter()
+            .map(|post| search::tokenize(&parser::plain_text(&post.content), &stop_words).collect())
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    (0..posts.len())
+        .map(|index| {
+            let mut scores: Vec<(usize, f64)> = (0..posts.len())
+                .filter(|other| *other != index)
+                .map(|other| {
+                    let mut score = tags[index].intersection(&tags[other]).count() as f64;
+                    if config.content_similarity {
+                        score += similarity(&terms[index], &terms[other]);
+                    }
+                    (other, score)
+                })
+                .filter(|(_, score)| *score > 0.0)
+                .collect();
+            scores.sort_by(|(a, a_score), (b, b_score)| {
+                b_score
+                    .total_cmp(a_score)
+                    .then_with(|| posts[*b].date.cmp(&posts[*a].date))
+                    .then_with(|| posts[*a].name.cmp(&posts[*b].name))
+            });
+            scores
+                .into_iter()
+                .take(config.limit)
+                .map(|(other, _)| RelatedPost {
+                    title: posts[other].title.clone(),
+                    url: posts[other].url.clone(),
+                    date: posts[other].date.clone(),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post(name: &str, date: &str, tags: &[&str], content: &str) -> Page {
+        crate::test_post(
+            &format!("content/post/{}.md", name),
+            &format!("{{title: {}, date: {}, tags: {:?}}}", name, date, tags),
+            content,
+        )
+    }
+
+    // Titles of the related posts of the first post
+    fn related_titles(posts: &[Page], config: &RelatedConfig) -> Vec<String> {
+        related_posts(posts, config, &[])
+            .remove(0)
+            .into_iter()
+            .filter_map(|related| related.title)
+            .collect()
+    }
+
+    #[test]
+    fn similarity_is_the_share_of_common_terms() {
+        let terms = |terms: &[&str]| -> HashSet<String> {
+            terms.iter().map(|term| term.to_string()).collect()
+        };
+        assert_eq!(similarity(&terms(&["a", "b"]), &terms(&["a", "b"])), 1.0);
+        assert_eq!(
+            similarity(&terms(&["a", "b"]), &terms(&["b", "c"])),
+            1.0 / 3.0
+        );
+        assert_eq!(similarity(&terms(&["a"]), &terms(&["b"])), 0.0);
+        assert_eq!(similarity(&terms(&[]), &terms(&[])), 0.0);
+    }
+
+    #[test]
+    fn posts_are_ordered_by_score() {
+        let posts = [
+            post("post", "2024-01-01", &["rust", "web", "cli"], ""),
+            post("one-tag", "2024-01-02", &["rust"], ""),
+            post("three-tags", "2024-01-03", &["rust", "web", "cli"], ""),
+            post("two-tags", "2024-01-04", &["web", "cli"], ""),
+        ];
+        assert_eq!(
+            related_titles(&posts, &RelatedConfig::default()),
+            ["three-tags", "two-tags", "one-tag"]
+        );
+    }
+
+    #[test]
+    fn content_similarity_breaks_ties_between_tags() {
+        let posts = [
+            post("post", "2024-01-01", &["rust"], "parsing markdown files"),
+            post("unrelated", "2024-01-03", &["rust"], "cooking pasta files"),
+            post(
+                "similar",
+                "2024-01-02",
+                &["rust"],
+                "parsing markdown quickly",
+            ),
+            post("untagged", "2024-01-04", &[], "parsing markdown files"),
+        ];
+        let config = RelatedConfig {
+            content_similarity: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            related_titles(&posts, &config),
+            ["similar", "unrelated", "untagged"]
+        );
+    }
+
+    #[test]
+    fn ties_are_ordered_by_date_then_name() {
+        let posts = [
+            post("post", "2024-01-01", &["rust"], ""),
+            post("older", "2023-01-01", &["rust"], ""),
+            post("newer-b", "2025-01-01", &["rust"], ""),
+            post("newer-a", "2025-01-01", &["rust"], ""),
+        ];
+        assert_eq!(
+            related_titles(&posts, &RelatedConfig::default()),
+            ["newer-a", "newer-b", "older"]
+        );
+    }
+
+    #[test]
+    fn limit_is_respected() {
+        let posts: Vec<Page> = (0..10)
+            .map(|index| post(&format!("post-{}", index), "2024-01-01", &["rust"], ""))
+            .collect();
+        let config = RelatedConfig {
+            limit: 2,
+            ..Default::default()
+        };
+        assert_eq!(related_titles(&posts, &config), ["post-1", "post-2"]);
+    }
+
+    #[test]
+    fn posts_without_shared_tags_are_left_out() {
+        let posts = [
+            post("post", "2024-01-01", &["rust"], "parsing markdown"),
+            post("other", "2024-01-02", &["cooking"], "parsing markdown"),
+            post("untagged", "2024-01-03", &[], "parsing markdown"),
+        ];
+        assert!(related_titles(&posts, &RelatedConfig::default()).is_empty());
+    }
+}
@@ -2,91 +2,646 @@
 // in the static folder). This also handles image optimization
 // workflows
 
-use crate::cache::CacheContext;
+use crate::cache::{self, CacheContext};
+use crate::error::BuildError;
+use crate::minify::Minifier;
+use globset::{Glob, GlobMatcher, GlobSet, GlobSetBuilder};
 use image::{DynamicImage, ImageFormat};
 use log::{info, warn};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::io::Result;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use walkdir::WalkDir;
 
-fn save_optimized_image(image: &DynamicImage, output_path: &Path) -> std::io::Result<()> {
+// Static resource settings from the configuration YAML (the `static` section)
+// - no_optimize: globs for files that are copied as-is instead of being converted to WebP
+//   (e.g. "*.svg", "*.gif")
+// - rules: per-pattern action, the first matching rule wins and takes precedence over
+//   no_optimize
+// Patterns are matched against the path relative to the static directory
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct StaticConfig {
+    #[serde(default)]
+    pub no_optimize: Vec<String>,
+    #[serde(default)]
+    pub rules: Vec<StaticRule>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StaticRule {
+    pub pattern: String,
+    pub action: StaticAction,
+}
+
+// What to do with a static file:
+// - Optimize: decode as an image and re-encode (resized WebP, or ICO for favicons)
+// - Copy: pass-through copy into the build folder
+// - Ignore: leave out of the build folder
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StaticAction {
+    Optimize,
+    Copy,
+    Ignore,
+}
+
+// Compiled version of the static configuration, used to decide the action for every file
+#[derive(Debug)]
+pub struct StaticRules {
+    rules: Vec<(GlobMatcher, StaticAction)>,
+    no_optimize: GlobSet,
+}
+
+impl StaticRules {
+    pub fn new(config: &StaticConfig) -> std::result::Result<Self, globset::Error> {
+        let mut rules = Vec::new();
+        for rule in &config.rules {
+            rules.push((Glob::new(&rule.pattern)?.compile_matcher(), rule.action));
+        }
+
+        let mut no_optimize = GlobSetBuilder::new();
+        for pattern in &config.no_optimize {
+            no_optimize.add(Glob::new(pattern)?);
+        }
+
+        Ok(StaticRules {
+            rules,
+            no_optimize: no_optimize.build()?,
+        })
+    }
+
+    // Files without a matching rule are only optimized when the extension is a known image
+    // format. Everything else (PDFs, fonts, CSS, JS, etc.) is copied through untouched
+    pub fn action_for(&self, relative_path: &Path) -> StaticAction {
+        if let Some((_, action)) = self
+            .rules
+            .iter()
+            .find(|(matcher, _)| matcher.is_match(relative_path))
+        {
+            return *action;
+        }
+
+        if self.no_optimize.is_match(relative_path) {
+            return StaticAction::Copy;
+        }
+
+        if ImageFormat::from_path(relative_path).is_ok() {
+            StaticAction::Optimize
+        } else {
+            StaticAction::Copy
+        }
+    }
+
+    // Path of the file once copied into the build folder. Optimized images (other than ICO
+    // favicons) are converted to WebP
+    pub fn output_path_for(&self, relative_path: &Path) -> PathBuf {
+        let is_ico = matches!(ImageFormat::from_path(relative_path), Ok(ImageFormat::Ico));
+        if self.action_for(relative_path) == StaticAction::Optimize && !is_ico {
+            relative_path.with_extension("webp")
+        } else {
+            relative_path.to_path_buf()
+        }
+    }
+}
+
+// Encode an image to the output path in the given format
+fn write_image(
+    image: &DynamicImage,
+    output_path: &Path,
+    format: ImageFormat,
+) -> image::ImageResult<()> {
+    let mut output_file = std::fs::File::create(output_path)?;
+    image.write_to(&mut output_file, format)
+}
+
+fn save_optimized_image(image: &DynamicImage, output_path: &Path) -> image::ImageResult<PathBuf> {
     // resize image to a max width (e.g., 1920px)
     let resized = image.resize(1920, 1080, image::imageops::FilterType::Lanczos3);
 
-    // generate a new file name with a WebP extension
+    // write the resized image as WebP (with a new file name using the WebP extension)
     let output_path = output_path.with_extension("webp");
+    write_image(&resized, &output_path, ImageFormat::WebP)?;
+    Ok(output_path)
+}
 
-    // write the resized image as WebP
-    let mut output_file = std::fs::File::create(output_path)?;
-    resized
-        .write_to(&mut output_file, ImageFormat::WebP)
-        .unwrap();
+// Decode & re-encode a single image. Returns the path of the optimized image, or None if the
+// file could not be decoded as an image
+fn optimize_image(path: &Path, static_output_path: &Path) -> Result<Option<PathBuf>, BuildError> {
+    let reader = image::io::Reader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| BuildError::io(path, e))?;
+    let format = reader.format().unwrap_or(ImageFormat::Png); // fallback if format is not detected
+    let Ok(image) = reader.decode() else {
+        return Ok(None);
+    };
 
-    Ok(())
+    let output_path = if format == ImageFormat::Ico {
+        // keep the favicon as an ICO file (browsers expect the original format)
+        let output_path = static_output_path.with_extension("ico");
+        write_image(&image, &output_path, ImageFormat::Ico).map(|_| output_path)
+    } else {
+        save_optimized_image(&image, static_output_path)
+    };
+    output_path.map(Some).map_err(|e| BuildError::Image {
+        path: path.to_path_buf(),
+        message: e.to_string(),
+    })
 }
 
-// Optimize & copy static folder
+// Optimize & copy static folder. Every file is processed even if some fail, and all of the
+// errors are returned. Stylesheets and scripts are minified on the way (see Minifier)
 pub fn optimize_and_copy_static_folder(
     static_path: &Path,
     static_output_path: &Path,
-    cache_path: &PathBuf,
-) -> Result<()> {
-    let cache_file = cache_path.clone();
-    let mut cache_context = CacheContext::load_or_default(cache_file).unwrap();
-
+    cache_context: &CacheContext,
+    rules: &StaticRules,
+    minifier: &Minifier,
+) -> Result<(), Vec<BuildError>> {
     if !static_path.exists() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            "Static folder does not exist",
-        ));
+        return Err(vec![BuildError::io(
+            static_path,
+            std::io::Error::new(std::io::ErrorKind::NotFound, "Static folder does not exist"),
+        )]);
     }
 
-    for entry in fs::read_dir(static_path)? {
-        let entry = entry?;
-        let path = entry.path();
-
-        if path.is_file() {
-            let file_name = path.file_name().unwrap();
-            let static_output_path = static_output_path.join(file_name);
+    process_static_folder(
+        static_path,
+        static_output_path,
+        rules,
+        cache_context,
+        minifier,
+        false,
+    )
+}
 
-            let file_name_path_buf = path.to_path_buf();
+// Optimize & copy the assets of a page bundle (every file in the bundle directory other than
+// the markdown files) into a folder named after the page in the build folder
+pub fn optimize_and_copy_bundle_assets(
+    bundle_path: &Path,
+    bundle_output_path: &Path,
+    cache_context: &CacheContext,
+    rules: &StaticRules,
+    minifier: &Minifier,
+) -> Result<(), Vec<BuildError>> {
+    process_static_folder(
+        bundle_path,
+        bundle_output_path,
+        rules,
+        cache_context,
+        minifier,
+        true,
+    )
+}
 
-            // check if the file is unmodified (already in build)
-            // if not, skip this file (continue), otherwise, update the cache
-            // and log reflecting that a new resource will save the optimized copy
-            if !cache_context.update_file_if_changed(&file_name_path_buf)? {
-                info!("Skipping already copied file: {:?}", file_name_path_buf);
+// Recursively walks through the folder, mirroring the directory structure into the output
+// folder, then optimizes/copies the files in parallel (image encoding is the slowest part of
+// the build)
+fn process_static_folder(
+    static_root: &Path,
+    static_output_path: &Path,
+    rules: &StaticRules,
+    cache_context: &CacheContext,
+    minifier: &Minifier,
+    skip_markdown: bool,
+) -> Result<(), Vec<BuildError>> {
+    let mut files = Vec::new();
+    let mut errors = Vec::new();
+    for entry in WalkDir::new(static_root) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                let path = e.path().unwrap_or(static_root).to_path_buf();
+                errors.push(BuildError::io(&path, e.into()));
                 continue;
-            } else {
-                info!("Copying/optimizing static file: {:?}", file_name_path_buf);
             }
+        };
+        let path = entry.path();
+        let relative_path = path.strip_prefix(static_root).unwrap_or(path);
 
-            let reader = image::io::Reader::open(&path)?;
-            let format = reader.format().unwrap_or(ImageFormat::Png); // fallback if format is not detected
-            match reader.decode() {
-                Ok(image) => {
-                    if format == ImageFormat::Ico {
-                        // generate a new file name with a WebP extension
-                        let output_path = static_output_path.with_extension("ico");
-
-                        // write the resized image as WebP
-                        let mut output_file = std::fs::File::create(output_path)?;
-                        image.write_to(&mut output_file, ImageFormat::Ico).unwrap();
-                    } else {
-                        save_optimized_image(&image, &static_output_path)?;
-                    }
-                }
-                Err(_err) => {
-                    warn!("Could not decode the following file as an image (optimization failed): {:?}", path);
-                }
+        if entry.file_type().is_dir() {
+            let output_dir = static_output_path.join(relative_path);
+            if let Err(e) = fs::create_dir_all(&output_dir) {
+                errors.push(BuildError::io(&output_dir, e));
+            }
+        } else if entry.file_type().is_file() {
+            if skip_markdown && path.extension().and_then(|e| e.to_str()) == Some("md") {
+                continue;
             }
-        } else if path.is_dir() {
-            // Recursively process subdirectories
-            let subfolder = static_output_path.join(path.file_name().unwrap());
-            fs::create_dir_all(&subfolder)?;
-            optimize_and_copy_static_folder(&path, &subfolder, &cache_path)?;
+            files.push(path.to_path_buf());
         }
     }
 
+    errors.par_extend(files.par_iter().filter_map(|path| {
+        let relative_path = path.strip_prefix(static_root).unwrap_or(path);
+        process_static_file(
+            path,
+            &static_output_path.join(relative_path),
+            rules.action_for(relative_path),
+            cache_context,
+            minifier,
+        )
+        .err()
+    }));
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn process_static_file(
+    path: &Path,
+    static_output_path: &Path,
+    action: StaticAction,
+    cache_context: &CacheContext,
+    minifier: &Minifier,
+) -> Result<(), BuildError> {
+    if action == StaticAction::Ignore {
+        info!("Ignoring static file: {:?}", path);
+        return Ok(());
+    }
+
+    // check if the file is unmodified (already in build)
+    // if not, skip this file (continue), otherwise, update the cache
+    // and log reflecting that a new resource will save the optimized copy
+    if !cache_context.update_file_if_changed(path)? {
+        info!("Skipping already copied file: {:?}", path);
+        return Ok(());
+    } else {
+        info!("Copying/optimizing static file: {:?}", path);
+    }
+
+    let output_path = match action {
+        StaticAction::Optimize => optimize_image(path, static_output_path)?,
+        _ => None,
+    };
+    let output_path = match output_path {
+        Some(output_path) => output_path,
+        None => {
+            if action == StaticAction::Optimize {
+                warn!(
+                    "Could not decode the following file as an image (copying it as-is): {:?}",
+                    path
+                );
+            }
+            copy_file(path, static_output_path, minifier)?;
+            static_output_path.to_path_buf()
+        }
+    };
+
+    // Keep track of the generated file, so it can be removed once the source file is deleted
+    cache_context.record_outputs(path, &[output_path]);
     Ok(())
 }
+
+// Copy a file as-is, or minified for stylesheets and scripts
+fn copy_file(path: &Path, output_path: &Path, minifier: &Minifier) -> Result<(), BuildError> {
+    if !minifier.minifies(path) {
+        fs::copy(path, output_path).map_err(|e| BuildError::io(path, e))?;
+        return Ok(());
+    }
+    let content = fs::read(path).map_err(|e| BuildError::io(path, e))?;
+    let minified = minifier.minify(path, &content).unwrap_or(content);
+    fs::write(output_path, minified).map_err(|e| BuildError::io(output_path, e))
+}
+
+// How an image is resized to the requested dimensions from a template:
+// - Fit: scale down to fit within the box, preserving the aspect ratio
+// - Fill: scale and crop so the image covers the exact box
+// - Crop: cut the box out of the centre of the image without scaling
+#[derive(Debug, Clone, Copy)]
+enum ResizeOp {
+    Fit,
+    Fill,
+    Crop,
+}
+
+impl ResizeOp {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "fit" => Some(ResizeOp::Fit),
+            "fill" => Some(ResizeOp::Fill),
+            "crop" => Some(ResizeOp::Crop),
+            _ => None,
+        }
+    }
+
+    fn apply(self, image: &DynamicImage, width: u32, height: u32) -> DynamicImage {
+        let filter = image::imageops::FilterType::Lanczos3;
+        match self {
+            ResizeOp::Fit => image.resize(width, height, filter),
+            ResizeOp::Fill => image.resize_to_fill(width, height, filter),
+            ResizeOp::Crop => {
+                let width = width.min(image.width());
+                let height = height.min(image.height());
+                let x = (image.width() - width) / 2;
+                let y = (image.height() - height) / 2;
+                image.crop_imm(x, y, width, height)
+            }
+        }
+    }
+}
+
+// Tera function to process images at build time from templates (the markdown content is not
+// rendered through Tera, so it is only available within templates):
+//
+//   {% set thumb = resize_image(path="images/cover.png", width=320, height=180, op="fill") %}
+//   <img src="{{ thumb.url }}" width="{{ thumb.width }}" height="{{ thumb.height }}" />
+//
+// Arguments:
+// - path: image path relative to the static directory, or relative to the content directory
+//   for images within a page bundle (e.g. "post/my-post/cover.png")
+// - width/height: target size in pixels (either defaults to the source size when omitted)
+// - op: one of "fit" (default), "fill" or "crop"
+// - format: output format extension, "webp" by default
+//
+// Results are saved under static/processed in the build folder, named after the hash of the
+// source content and the arguments, so an image is only encoded again when either changes
+// The names of the images used during the build are collected (see prune_processed_images)
+pub struct ResizeImage {
+    static_dir: PathBuf,
+    content_dir: PathBuf,
+    output_dir: PathBuf,
+    used_files: Arc<Mutex<HashSet<String>>>,
+}
+
+impl ResizeImage {
+    pub fn new(static_dir: &Path, content_dir: &Path, output_dir: &Path) -> Self {
+        ResizeImage {
+            static_dir: static_dir.to_path_buf(),
+            content_dir: content_dir.to_path_buf(),
+            output_dir: output_dir.to_path_buf(),
+            used_files: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    // Shared set of the processed images used by templates during the build (the function
+    // itself is moved into Tera once registered)
+    pub fn used_files(&self) -> Arc<Mutex<HashSet<String>>> {
+        Arc::clone(&self.used_files)
+    }
+
+    // Static files take precedence over page bundle assets with the same path
+    fn resolve_source(&self, path: &str) -> Option<PathBuf> {
+        let path = path.trim_start_matches('/');
+        [&self.static_dir, &self.content_dir]
+            .iter()
+            .map(|dir| dir.join(path))
+            .find(|source| source.is_file())
+    }
+}
+
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+fn optional_u32_arg(args: &HashMap<String, tera::Value>, name: &str) -> tera::Result<Option<u32>> {
+    match args.get(name) {
+        None => Ok(None),
+        Some(value) => value
+            .as_u64()
+            .and_then(|value| u32::try_from(value).ok())
+            .filter(|value| *value > 0)
+            .map(Some)
+            .ok_or_else(|| {
+                tera::Error::msg(format!(
+                    "`resize_image`: `{}` must be a positive integer",
+                    name
+                ))
+            }),
+    }
+}
+
+impl tera::Function for ResizeImage {
+    fn call(&self, args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+        let path = args
+            .get("path")
+            .and_then(|value| value.as_str())
+            .ok_or_else(|| tera::Error::msg("`resize_image`: missing `path` argument"))?;
+        let width = optional_u32_arg(args, "width")?;
+        let height = optional_u32_arg(args, "height")?;
+        let op_name = args
+            .get("op")
+            .and_then(|value| value.as_str())
+            .unwrap_or("fit");
+        let op = ResizeOp::from_name(op_name).ok_or_else(|| {
+            tera::Error::msg(format!(
+                "`resize_image`: unknown op `{}` (expected fit, fill or crop)",
+                op_name
+            ))
+        })?;
+        let extension = args
+            .get("format")
+            .and_then(|value| value.as_str())
+            .unwrap_or("webp");
+        let format = ImageFormat::from_extension(extension).ok_or_else(|| {
+            tera::Error::msg(format!("`resize_image`: unknown format `{}`", extension))
+        })?;
+
+        let source = self.resolve_source(path).ok_or_else(|| {
+            tera::Error::msg(format!("`resize_image`: image `{}` not found", path))
+        })?;
+        let content = fs::read(&source).map_err(tera::Error::msg)?;
+
+        // cache key: source content + every argument that affects the output
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&content);
+        hasher.update(format!("{:?}|{:?}|{:?}|{}", width, height, op, extension).as_bytes());
+        let hash = hasher.finalize().to_hex();
+        let file_name = format!("{}.{}", &hash[..16], extension);
+
+        let processed_dir = self.output_dir.join("static/processed");
+        let output_path = processed_dir.join(&file_name);
+        self.used_files.lock().unwrap().insert(file_name.clone());
+
+        let (output_width, output_height) = if output_path.exists() {
+            image::image_dimensions(&output_path).map_err(tera::Error::msg)?
+        } else {
+            info!("Processing image {:?} into {:?}", source, output_path);
+            let image = image::load_from_memory(&content).map_err(tera::Error::msg)?;
+            let resized = op.apply(
+                &image,
+                width.unwrap_or(image.width()),
+                height.unwrap_or(image.height()),
+            );
+            fs::create_dir_all(&processed_dir).map_err(tera::Error::msg)?;
+
+            // Pages are rendered in parallel, so the same image may be processed by two threads
+            // at once. Write to a unique temporary file, then move it into place
+            let temp_path = processed_dir.join(format!(
+                "{}.{}.tmp",
+                file_name,
+                TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            write_image(&resized, &temp_path, format).map_err(tera::Error::msg)?;
+            fs::rename(&temp_path, &output_path).map_err(tera::Error::msg)?;
+            (resized.width(), resized.height())
+        };
+
+        Ok(serde_json::json!({
+            "url": format!("./static/processed/{}", file_name),
+            "width": output_width,
+            "height": output_height,
+        }))
+    }
+}
+
+// Remove the images processed by resize_image during a previous build that were not used by any
+// template during this build. Only valid when every page was rendered during this build
+pub fn prune_processed_images(
+    output_dir: &Path,
+    used_files: &HashSet<String>,
+    dry_run: bool,
+) -> Result<Vec<PathBuf>, BuildError> {
+    let processed_dir = output_dir.join("static/processed");
+    if !processed_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let io_error = |e| BuildError::io(&processed_dir, e);
+    let mut stale_files = Vec::new();
+    for entry in fs::read_dir(&processed_dir).map_err(io_error)? {
+        let path = entry.map_err(io_error)?.path();
+        let is_used = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| used_files.contains(name));
+        if !is_used {
+            stale_files.push(path);
+        }
+    }
+    stale_files.sort();
+
+    for path in &stale_files {
+        cache::remove_stale_file(path, dry_run)?;
+    }
+    Ok(stale_files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(name: &str, value: tera::Value) -> HashMap<String, tera::Value> {
+        HashMap::from([(name.to_string(), value)])
+    }
+
+    #[test]
+    fn optional_u32_arg_accepts_positive_integers() {
+        assert_eq!(optional_u32_arg(&HashMap::new(), "width").unwrap(), None);
+        assert_eq!(
+            optional_u32_arg(&args("width", 800.into()), "width").unwrap(),
+            Some(800)
+        );
+    }
+
+    #[test]
+    fn optional_u32_arg_rejects_other_values() {
+        for value in [
+            tera::Value::from(0),
+            tera::Value::from(-1),
+            tera::Value::from(1.5),
+            tera::Value::from("800"),
+            tera::Value::from(u64::from(u32::MAX) + 1),
+        ] {
+            let error = optional_u32_arg(&args("width", value.clone()), "width").unwrap_err();
+            assert!(
+                error.to_string().contains("must be a positive integer"),
+                "{:?}",
+                value
+            );
+        }
+    }
+
+    fn static_rules(yaml: &str) -> StaticRules {
+        StaticRules::new(&serde_yaml::from_str(yaml).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn only_images_are_optimized() {
+        let rules = static_rules("{}");
+        for (path, action, output_path) in [
+            (
+                "images/photo.png",
+                StaticAction::Optimize,
+                "images/photo.webp",
+            ),
+            (
+                "images/photo.JPG",
+                StaticAction::Optimize,
+                "images/photo.webp",
+            ),
+            ("favicon.ico", StaticAction::Optimize, "favicon.ico"),
+            ("files/resume.pdf", StaticAction::Copy, "files/resume.pdf"),
+            ("fonts/inter.woff2", StaticAction::Copy, "fonts/inter.woff2"),
+            ("styles/site.css", StaticAction::Copy, "styles/site.css"),
+        ] {
+            assert_eq!(rules.action_for(Path::new(path)), action, "{}", path);
+            assert_eq!(
+                rules.output_path_for(Path::new(path)),
+                Path::new(output_path),
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn first_matching_rule_wins_over_no_optimize() {
+        let rules = static_rules(
+            "no_optimize: ['*.gif', 'raw/**']
+rules:
+  - {pattern: 'raw/keep/*.png', action: optimize}
+  - {pattern: 'raw/**', action: ignore}
+  - {pattern: '*.png', action: copy}",
+        );
+        for (path, action) in [
+            ("animation.gif", StaticAction::Copy),
+            ("raw/keep/photo.png", StaticAction::Optimize),
+            ("raw/photo.jpg", StaticAction::Ignore),
+            ("images/photo.png", StaticAction::Copy),
+            ("images/photo.jpg", StaticAction::Optimize),
+        ] {
+            assert_eq!(rules.action_for(Path::new(path)), action, "{}", path);
+        }
+        assert_eq!(
+            rules.output_path_for(Path::new("images/photo.png")),
+            Path::new("images/photo.png")
+        );
+    }
+
+    #[test]
+    fn undecodable_images_are_copied_as_is() {
+        let root = std::env::temp_dir().join(format!("ssg-static-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let (static_dir, output_dir) = (root.join("static"), root.join("build"));
+        fs::create_dir_all(&static_dir).unwrap();
+        DynamicImage::new_rgb8(2, 2)
+            .save(static_dir.join("photo.png"))
+            .unwrap();
+        fs::write(static_dir.join("empty.png"), "").unwrap();
+        fs::write(static_dir.join("resume.pdf"), "%PDF").unwrap();
+
+        let cache_context = CacheContext::load_or_default(root.join("cache.json"), &root);
+        optimize_and_copy_static_folder(
+            &static_dir,
+            &output_dir,
+            &cache_context,
+            &static_rules("{}"),
+            &Minifier::new(&Default::default()),
+        )
+        .unwrap();
+
+        let mut outputs: Vec<String> = fs::read_dir(&output_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        outputs.sort();
+        assert_eq!(outputs, ["empty.png", "photo.webp", "resume.pdf"]);
+        assert!(fs::read(output_dir.join("empty.png")).unwrap().is_empty());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,366 @@
+// Client-side search: an inverted index of the posts (term -> the posts containing it, with a
+// score), written as JSON to the build folder and queried by the script of
+// partials/search.html. No server is involved
+//
+// Every post is indexed from its title, tags, description and the plain text of its markdown
+// content. The terms of each post are cached along with the hash of those inputs, so only new
+// and changed posts are tokenized again
+use crate::cache;
+use crate::config::Config;
+use crate::error::BuildError;
+use crate::parser;
+use crate::Page;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Version of the search cache & index file formats
+const SEARCH_VERSION: u32 = 2;
+
+const SEARCH_CACHE: &str = "search-cache.json";
+
+// Fields of a page that can be indexed
+const FIELDS: [&str; 4] = ["title", "tags", "description", "content"];
+
+// Terms shorter than this are left out of the index (and of queries)
+const MIN_TERM_LENGTH: usize = 2;
+
+const DEFAULT_STOP_WORDS: [&str; 48] = [
+    "a", "about", "after", "all", "also", "an", "and", "any", "are", "as", "at", "be", "been",
+    "but", "by", "can", "for", "from", "had", "has", "have", "how", "if", "in", "into", "is", "it",
+    "its", "just", "not", "of", "on", "or", "so", "than", "that", "the", "their", "then", "there",
+    "these", "this", "to", "was", "were", "what", "which", "with",
+];
+
+// Search settings from the configuration YAML (the `search` section)
+// - output: the index, relative to the build folder
+// - fields: the indexed fields (title, tags, description and content) and their weight within
+//   the score of a post
+// - stop_words: common words left out of the index (and ignored within queries)
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SearchConfig {
+    pub enabled: bool,
+    pub output: PathBuf,
+    pub fields: BTreeMap<String, u32>,
+    pub stop_words: Vec<String>,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            enabled: false,
+            output: PathBuf::from("search-index.json"),
+            fields: BTreeMap::from([
+                ("title".to_string(), 10),
+                ("tags".to_string(), 5),
+                ("description".to_string(), 3),
+                ("content".to_string(), 1),
+            ]),
+            stop_words: DEFAULT_STOP_WORDS
+                .iter()
+                .map(|word| word.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl SearchConfig {
+    pub fn validate(&self) -> Vec<String> {
+        self.fields
+            .keys()
+            .filter(|field| !FIELDS.contains(&field.as_str()))
+            .map(|field| {
+                format!(
+                    "`search.fields`: unknown field `{}` (expected one of {})",
+                    field,
+                    FIELDS.join(", ")
+                )
+            })
+            .collect()
+    }
+
+    // URL of the index relative to the generated pages (for the templates)
+    pub fn index_url(&self) -> Option<String> {
+        self.enabled.then(|| format!("./{}", self.output.display()))
+    }
+}
+
+// A post within the index. The terms are only part of the cache (the index groups them by term)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Document {
+    title: String,
+    url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedDocument {
+    hash: String,
+    document: Document,
+    terms: BTreeMap<String, u32>,
+}
+
+// Cached documents, keyed by the markdown file of the post. Discarded when the settings (fields
+// or stop words) change
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SearchCache {
+    version: u32,
+    settings: String,
+    documents: BTreeMap<PathBuf, CachedDocument>,
+}
+
+// The index: the documents, and for every term, the pairs of document number & score (flattened,
+// to keep the file small)
+#[derive(Debug, Serialize)]
+struct SearchIndex<'a> {
+    version: u32,
+    min_term_length: usize,
+    stop_words: &'a [String],
+    documents: Vec<&'a Document>,
+    terms: BTreeMap<&'a str, Vec<u32>>,
+}
+
+// Lowercase words of the text, without the stop words and the shortest terms (by characters,
+// rather than bytes). Queries are split the same way by partials/search.html, which reads the
+// minimum length from the index
+pub fn tokenize<'a>(
+    text: &'a str,
+    stop_words: &'a HashSet<&str>,
+) -> impl Iterator<Item = String> + 'a {
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(str::to_lowercase)
+        .filter(move |term| {
+            term.chars().count() >= MIN_TERM_LENGTH && !stop_words.contains(term.as_str())
+        })
+}
+
+// Text of an indexed field of the page
+fn field_text(page: &Page, field: &str) -> String {
+    match field {
+        "title" => page.title.clone().unwrap_or_default(),
+        "tags" => page.tags.as_deref().unwrap_or_default().join(" "),
+        "description" => page.description.clone().unwrap_or_default(),
+        _ => parser::plain_text(&page.content),
+    }
+}
+
+// Hash of everything the document of a page depends on
+fn page_hash(page: &Page) -> String {
+    let mut hasher = blake3::Hasher::new();
+    for value in [
+        page.title.as_deref(),
+        page.url.as_deref(),
+        page.date.as_deref(),
+        page.description.as_deref(),
+        Some(&page.content),
+    ] {
+        hasher.update(format!("{:?}\0", value).as_bytes());
+    }
+    hasher.update(format!("{:?}", page.tags).as_bytes());
+    hasher.finalize().to_hex().to_string()
+}
+
+fn index_document(page: &Page, config: &SearchConfig) -> CachedDocument {
+    let stop_words: HashSet<&str> = config.stop_words.iter().map(String::as_str).collect();
+    let mut terms: BTreeMap<String, u32> = BTreeMap::new();
+    for (field, weight) in &config.fields {
+        for term in tokenize(&field_text(page, field), &stop_words) {
+            *terms.entry(term).or_default() += weight;
+        }
+    }
+    CachedDocument {
+        hash: page_hash(page),
+        document: Document {
+            title: page.title.clone().unwrap_or_else(|| page.name.clone()),
+            url: page.url.clone().unwrap_or_default(),
+            date: page.date.clone(),
+            description: page.description.clone(),
+        },
+        terms,
+    }
+}
+
+fn load_cache(path: &Path, settings: &str) -> BTreeMap<PathBuf, CachedDocument> {
+    let Ok(data) = fs::read_to_string(path) else {
+        return BTreeMap::new();
+    };
+    match serde_json::from_str::<SearchCache>(&data) {
+        Ok(cache) if cache.version == SEARCH_VERSION && cache.settings == settings => {
+            cache.documents
+        }
+        Ok(_) => {
+            info!("Search settings changed. Indexing every post");
+            BTreeMap::new()
+        }
+        Err(_) => {
+            warn!("Discarding unreadable search cache {:?}", path);
+            BTreeMap::new()
+        }
+    }
+}
+
+// Write the search index of the posts (only when it changed). Returns the cache of the
+// indexed posts, to save once the build succeeded (see save_cache), or None when search is
+// disabled
+pub fn build_search_index(
+    config: &Config,
+    posts: &[Page],
+) -> Result<Option<SearchCache>, BuildError> {
+    let search = &config.search;
+    let output_dir = &config.paths.output_dir;
+    let index_path = output_dir.join(&search.output);
+    let cache_path = output_dir.join(SEARCH_CACHE);
+    if !search.enabled {
+        return Ok(None);
+    }
+
+    let settings = serde_json::to_string(&(&search.fields, &search.stop_words))
+        .map_err(|e| BuildError::io(&cache_path, std::io::Error::other(e)))?;
+    let mut cached = load_cache(&cache_path, &settings);
+    // Documents are numbered in the order of the posts
+    let mut documents = Vec::new();
+    let mut indexed = 0;
+    for page in posts {
+        let key = page
+            .source
+            .strip_prefix(&config.paths.root_dir)
+            .unwrap_or(&page.source)
+            .to_path_buf();
+        let document = match cached.remove(&key) {
+            Some(document) if document.hash == page_hash(page) => document,
+            _ => {
+                indexed += 1;
+                index_document(page, search)
+            }
+        };
+        documents.push((key, document));
+    }
+    info!(
+        "Search index: {} post(s), {} indexed again",
+        documents.len(),
+        indexed
+    );
+
+    let mut terms: BTreeMap<&str, Vec<u32>> = BTreeMap::new();
+    for (number, (_, document)) in documents.iter().enumerate() {
+        for (term, score) in &document.terms {
+            terms
+                .entry(term)
+                .or_default()
+                .extend([number as u32, *score]);
+        }
+    }
+    let index = SearchIndex {
+        version: SEARCH_VERSION,
+        min_term_length: MIN_TERM_LENGTH,
+        stop_words: &search.stop_words,
+        documents: documents
+            .iter()
+            .map(|(_, document)| &document.document)
+            .collect(),
+        terms,
+    };
+
+    let io_error = |path: &Path, e| BuildError::io(path, e);
+    let json = serde_json::to_string(&index).map_err(std::io::Error::other);
+    let json = json.map_err(|e| io_error(&index_path, e))?;
+    if fs::read_to_string(&index_path).ok().as_deref() != Some(json.as_str()) {
+        cache::write_file_atomic(&index_path, json.as_bytes())
+            .map_err(|e| io_error(&index_path, e))?;
+    }
+
+    Ok(Some(SearchCache {
+        version: SEARCH_VERSION,
+        settings,
+        documents: documents.into_iter().collect(),
+    }))
+}
+
+pub fn save_cache(config: &Config, cache: &SearchCache) -> Result<(), BuildError> {
+    let cache_path = config.paths.output_dir.join(SEARCH_CACHE);
+    let json = serde_json::to_string(cache).map_err(std::io::Error::other);
+    json.and_then(|json| cache::write_file_atomic(&cache_path, json.as_bytes()))
+        .map_err(|e| BuildError::io(&cache_path, e))
+}
+
+// When search is disabled, the index and the cache of previous builds are stale (with
+// dry_run, they are only reported). Returns the stale files
+pub fn prune(config: &Config, dry_run: bool) -> Result<Vec<PathBuf>, BuildError> {
+    let output_dir = &config.paths.output_dir;
+    if config.search.enabled {
+        return Ok(Vec::new());
+    }
+    let stale_files: Vec<PathBuf> = [
+        output_dir.join(&config.search.output),
+        output_dir.join(SEARCH_CACHE),
+    ]
+    .into_iter()
+    .filter(|path| path.exists())
+    .collect();
+    for path in &stale_files {
+        cache::remove_stale_file(path, dry_run)?;
+    }
+    Ok(stale_files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokenize_folds_case_and_skips_short_terms_and_stop_words() {
+        let stop_words = HashSet::from(["the"]);
+        let terms: Vec<String> = tokenize("The Rust-CLI, a ÉTÉ x2 y", &stop_words).collect();
+        assert_eq!(terms, ["rust", "cli", "été", "x2"]);
+    }
+
+    #[test]
+    fn rebuilding_indexes_only_the_changed_posts() {
+        let output_dir = std::env::temp_dir().join(format!("ssg-search-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&output_dir);
+        let config: Config = serde_yaml::from_str(&format!(
+            "metadata: {{base_url: ''}}\nsearch: {{enabled: true}}\npaths: {{output_dir: {:?}}}",
+            output_dir
+        ))
+        .unwrap();
+        let post = |name: &str, content: &str| {
+            crate::test_post(
+                &format!("content/post/{}.md", name),
+                &format!("{{title: {}}}", name),
+                content,
+            )
+        };
+
+        let mut cache =
+            build_search_index(&config, &[post("first", "rust"), post("second", "css")])
+                .unwrap()
+                .unwrap();
+        assert!(output_dir.join(&config.search.output).exists());
+        // Mark the cached terms, to tell the documents taken from the cache apart
+        for document in cache.documents.values_mut() {
+            document.terms.insert("cached".to_string(), 1);
+        }
+        save_cache(&config, &cache).unwrap();
+
+        let cache = build_search_index(&config, &[post("first", "rust"), post("second", "html")])
+            .unwrap()
+            .unwrap();
+        let terms = |name: &str| {
+            let key = PathBuf::from(format!("content/post/{}.md", name));
+            cache.documents[&key]
+                .terms
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(terms("first"), ["cached", "first", "rust"]);
+        assert_eq!(terms("second"), ["html", "second"]);
+        fs::remove_dir_all(&output_dir).unwrap();
+    }
+}
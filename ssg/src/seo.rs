@@ -0,0 +1,274 @@
+// Metadata for search engines and social media link previews (Open Graph & Twitter Cards),
+// computed for every page and exposed to the templates as `seo` (see partials/seo.html), along
+// with schema.org structured data (JSON-LD) exposed as `json_ld`
+use crate::assets::AssetManifest;
+use crate::config::SiteMetadata;
+use crate::parser;
+use crate::resources::StaticRules;
+use crate::{Page, PageType};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::path::Path;
+
+// Length of the description generated from the content of pages without one (search engines
+// cut descriptions off at around 160 characters)
+const SUMMARY_LENGTH: usize = 160;
+
+// Elements whose text is left out of the generated description
+const SKIPPED_ELEMENTS: [&str; 9] = ["pre", "h1", "h2", "h3", "h4", "h5", "h6", "script", "style"];
+
+#[derive(Debug, Serialize)]
+pub struct Seo {
+    pub title: String,
+    pub description: String,
+    pub canonical_url: String,
+    pub site_name: String,
+    // absolute URL of the preview image, if any
+    pub image: Option<String>,
+    // Open Graph type: "article" for posts, "website" for everything else
+    pub og_type: &'static str,
+    // publication date of posts (YYYY-MM-DD, followed by the time when given)
+    pub published_time: Option<String>,
+    pub tags: Vec<String>,
+    // "summary_large_image" when there is a preview image, "summary" otherwise
+    pub twitter_card: &'static str,
+}
+
+// Compute the SEO metadata of a page from its frontmatter, falling back to the site metadata.
+// The description falls back to a summary of the rendered content
+pub fn page_seo(
+    site: &SiteMetadata,
+    static_rules: &StaticRules,
+    assets: &AssetManifest,
+    page: &Page,
+) -> Seo {
+    let base_url = site.base_url.trim_end_matches('/');
+    let canonical_url = match page.page_type {
+        PageType::Index => format!("{}/", base_url),
+        _ => page.url.clone().unwrap_or_else(|| format!("{}/", base_url)),
+    };
+
+    let description = page
+        .description
+        .clone()
+        .filter(|description| !description.trim().is_empty())
+        .or_else(|| Some(summarize(&page.summary, SUMMARY_LENGTH)))
+        .filter(|description| !description.is_empty())
+        .unwrap_or_else(|| site.description.clone());
+
+    // Images are given relative to the static folder (like the images within the content), and
+    // must be absolute URLs for link previews
+    let image = page
+        .image
+        .as_deref()
+        .or(site.image.as_deref())
+        .map(|image| {
+            if image.starts_with("http://") || image.starts_with("https://") {
+                image.to_string()
+            } else {
+                let path = Path::new(image.trim_start_matches('/'));
+                format!("{}/static/{}", base_url, assets.resolve(static_rules, path))
+            }
+        });
+
+    let is_post = matches!(page.page_type, PageType::Post);
+    Seo {
+        title: page.title.clone().unwrap_or_else(|| site.title.clone()),
+        description,
+        canonical_url,
+        site_name: site.title.clone(),
+        og_type: if is_post { "article" } else { "website" },
+        published_time: page.date.as_deref().filter(|_| is_post).and_then(iso_date),
+        tags: page.tags.clone().unwrap_or_default(),
+        twitter_card: if image.is_some() {
+            "summary_large_image"
+        } else {
+            "summary"
+        },
+        image,
+    }
+}
+
+// schema.org structured data for a page, ready to be embedded within a
+// <script type="application/ld+json"> element:
+// - Index: the author of the site (Person)
+// - Post: the article (BlogPosting) and its position within the site (BreadcrumbList)
+// - Page: its position within the site (BreadcrumbList)
+pub fn json_ld(site: &SiteMetadata, page: &Page, seo: &Seo) -> String {
+    let home_url = format!("{}/", site.base_url.trim_end_matches('/'));
+    let author = json!({
+        "@type": "Person",
+        "name": site.author,
+        "url": home_url,
+    });
+
+    let mut graph = Vec::new();
+    match page.page_type {
+        PageType::Index => {
+            let mut person = author;
+            person["description"] = Value::from(seo.description.as_str());
+            graph.push(person);
+        }
+        PageType::Post | PageType::Page => {
+            if matches!(page.page_type, PageType::Post) {
+                let mut posting = json!({
+                    "@type": "BlogPosting",
+                    "headline": seo.title,
+                    "description": seo.description,
+                    "url": seo.canonical_url,
+                    "mainEntityOfPage": seo.canonical_url,
+                    "author": author,
+                });
+                if let Some(published_time) = &seo.published_time {
+                    posting["datePublished"] = Value::from(published_time.as_str());
+                }
+                if !seo.tags.is_empty() {
+                    posting["keywords"] = Value::from(seo.tags.join(", "));
+                }
+                if let Some(image) = &seo.image {
+                    posting["image"] = Value::from(image.as_str());
+                }
+                graph.push(posting);
+            }
+            graph.push(json!({
+                "@type": "BreadcrumbList",
+                "itemListElement": [
+                    {
+                        "@type": "ListItem",
+                        "position": 1,
+                        "name": site.title,
+                        "item": home_url,
+                    },
+                    {
+                        "@type": "ListItem",
+                        "position": 2,
+                        "name": seo.title,
+                        "item": seo.canonical_url,
+                    },
+                ],
+            }));
+        }
+        PageType::Unknown => {}
+    }
+
+    let data = json!({
+        "@context": "https://schema.org",
+        "@graph": graph,
+    });
+    // "</" would end the script element early
+    data.to_string().replace("</", "<\\/")
+}
+
+// Dates within the frontmatter are written as MM-DD-YYYY. Dates already written as YYYY-MM-DD
+// are returned as-is. A time may follow the date (e.g. `05-06-2025 10:30` or
+// `2025-05-06T10:30:00Z`), which is kept after a `T`
+pub fn iso_date(date: &str) -> Option<String> {
+    let date = date.trim();
+    let (date, time) = match date.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time.trim())),
+        None => (date, None),
+    };
+    let parts: Vec<&str> = date.split('-').collect();
+    let is_number = |part: &str, digits: usize| {
+        part.len() == digits && part.chars().all(|c| c.is_ascii_digit())
+    };
+    let date = match parts.as_slice() {
+        [month, day, year] if is_number(month, 2) && is_number(day, 2) && is_number(year, 4) => {
+            format!("{}-{}-{}", year, month, day)
+        }
+        [year, month, day] if is_number(year, 4) && is_number(month, 2) && is_number(day, 2) => {
+            format!("{}-{}-{}", year, month, day)
+        }
+        _ => return None,
+    };
+    match time {
+        Some(time) if time.starts_with(|c: char| c.is_ascii_digit()) => {
+            Some(format!("{}T{}", date, time))
+        }
+        Some(_) => None,
+        None => Some(date),
+    }
+}
+
+// Plain text of the rendered HTML (without headings and code), cut off at a word boundary
+pub fn summarize(html: &str, max_length: usize) -> String {
+    let text = parser::html_text(html, &SKIPPED_ELEMENTS);
+    if text.chars().count() <= max_length {
+        return text;
+    }
+
+    let mut summary = String::new();
+    for word in text.split(' ') {
+        if summary.chars().count() + word.chars().count() + 1 > max_length {
+            break;
+        }
+        if !summary.is_empty() {
+            summary.push(' ');
+        }
+        summary.push_str(word);
+    }
+    let summary = summary.trim_end_matches(|c: char| c.is_ascii_punctuation());
+    format!("{}…", summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn site() -> SiteMetadata {
+        serde_yaml::from_str("{base_url: 'https://example.com', title: Site, author: Me}").unwrap()
+    }
+
+    #[test]
+    fn json_ld_escapes_the_end_of_the_script_element() {
+        let post = crate::test_post(
+            "content/post/post.md",
+            "{title: 'A </script><script>alert(1)</script> title', date: 05-06-2025}",
+            "",
+        );
+        let seo = page_seo(
+            &site(),
+            &StaticRules::new(&Default::default()).unwrap(),
+            &AssetManifest::load_or_default(Path::new("missing"), &Default::default()),
+            &post,
+        );
+        let json_ld = json_ld(&site(), &post, &seo);
+        assert!(!json_ld.contains("</"));
+        assert!(json_ld.contains("A <\\/script><script>alert(1)<\\/script> title"));
+        let data: Value = serde_json::from_str(&json_ld).unwrap();
+        assert_eq!(
+            data["@graph"][0]["headline"],
+            "A </script><script>alert(1)</script> title"
+        );
+        assert_eq!(data["@graph"][0]["datePublished"], "2025-05-06");
+    }
+
+    #[test]
+    fn iso_date_reorders_dates() {
+        assert_eq!(iso_date("05-06-2025").as_deref(), Some("2025-05-06"));
+        assert_eq!(iso_date(" 2025-05-06 ").as_deref(), Some("2025-05-06"));
+        assert_eq!(iso_date("May 6, 2025"), None);
+        assert_eq!(iso_date("5-6-2025"), None);
+    }
+
+    #[test]
+    fn iso_date_keeps_the_time() {
+        assert_eq!(
+            iso_date("05-06-2025 10:30").as_deref(),
+            Some("2025-05-06T10:30")
+        );
+        assert_eq!(
+            iso_date("2025-05-06T10:30:00Z").as_deref(),
+            Some("2025-05-06T10:30:00Z")
+        );
+        assert_eq!(iso_date("2025-05-06 noon"), None);
+    }
+
+    #[test]
+    fn summarize_cuts_on_a_word_boundary() {
+        let html = "<h1>Heading</h1><p>The quick brown fox, jumps over</p><pre>code</pre>";
+        assert_eq!(summarize(html, 100), "The quick brown fox, jumps over");
+        assert_eq!(summarize(html, 21), "The quick brown fox…");
+        assert_eq!(summarize(html, 18), "The quick brown…");
+    }
+}
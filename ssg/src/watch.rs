@@ -0,0 +1,255 @@
+// Rebuilds the site whenever one of its sources changes (`ssg watch`). The modification times of
+// the content, templates, static files, configuration files and processor inputs are polled
+// rather than relying on file system notifications, which would need another dependency (and
+// behave differently on every platform)
+use crate::cli::Args;
+use crate::config::{self, Config};
+use crate::error::{self, BuildError};
+use log::warn;
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use walkdir::WalkDir;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// Modification time & size of every watched file
+type Snapshot = BTreeMap<PathBuf, (SystemTime, u64)>;
+
+// Files the build depends on. Without a valid configuration, only the configuration files are
+// watched (so that the build starts again once they are fixed)
+struct Watched {
+    config_dir: PathBuf,
+    dirs: Vec<PathBuf>,
+    files: Vec<PathBuf>,
+    output_dir: Option<PathBuf>,
+}
+
+impl Watched {
+    fn new(config_path: &Path, config: Option<&Config>) -> Self {
+        let config_dir = config_path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        let Some(config) = config else {
+            return Watched {
+                config_dir,
+                dirs: Vec::new(),
+                files: Vec::new(),
+                output_dir: None,
+            };
+        };
+
+        let paths = &config.paths;
+        let mut files = Vec::new();
+        for processor in &config.processors {
+            match processor.input_files(&paths.root_dir, &paths.output_dir) {
+                Ok(inputs) => files.extend(inputs),
+                Err(e) => warn!("{}", e),
+            }
+        }
+        Watched {
+            config_dir,
+            dirs: vec![
+                paths.content_dir.clone(),
+                paths.template_dir.clone(),
+                paths.static_dir.clone(),
+            ],
+            files,
+            output_dir: Some(paths.output_dir.clone()),
+        }
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        // The configuration file and its environment overlays
+        let config_files = fs::read_dir(&self.config_dir)
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| {
+                matches!(
+                    path.extension().and_then(OsStr::to_str),
+                    Some("yml" | "yaml")
+                )
+            });
+        let dir_files = self.dirs.iter().flat_map(|dir| {
+            WalkDir::new(dir)
+                .into_iter()
+                .filter_map(Result::ok)
+                .filter(|entry| entry.file_type().is_file())
+                .map(|entry| entry.into_path())
+        });
+
+        config_files
+            .chain(dir_files)
+            .chain(self.files.iter().cloned())
+            .filter(|path| {
+                self.output_dir
+                    .as_ref()
+                    .is_none_or(|output_dir| !path.starts_with(output_dir))
+            })
+            .filter_map(|path| {
+                let metadata = fs::metadata(&path).ok()?;
+                Some((path, (metadata.modified().ok()?, metadata.len())))
+            })
+            .collect()
+    }
+}
+
+// Files added, removed or modified between two snapshots
+fn changed_files(previous: &Snapshot, current: &Snapshot) -> Vec<PathBuf> {
+    let mut changed: Vec<PathBuf> = current
+        .iter()
+        .filter(|(path, metadata)| previous.get(*path) != Some(*metadata))
+        .map(|(path, _)| path.clone())
+        .collect();
+    changed.extend(
+        previous
+            .keys()
+            .filter(|path| !current.contains_key(*path))
+            .cloned(),
+    );
+    changed
+}
+
+// Build the site, then rebuild it every time a watched file changes. Runs until interrupted
+//
+// Pages are only rendered again when their markdown file changed (as with any build), except
+// after a change to the templates or the settings they are rendered with, where every page is
+// rendered again (see the settings of the page cache)
+pub fn watch(args: &Args, build: impl Fn(Config) -> Result<(), Vec<BuildError>>) -> ! {
+    let config_path = config::config_path();
+
+    loop {
+        let started = Instant::now();
+        let watched = match config::retrieve_configuration(args.env.as_deref()) {
+            Ok(config) => {
+                let watched = Watched::new(&config_path, Some(&config));
+                let root_dir = config.paths.root_dir.clone();
+                match build(config) {
+                    Ok(()) => println!("Site built in {:.2?}", started.elapsed()),
+                    Err(errors) => error::print_summary("Build failed", &errors, &root_dir),
+                }
+                watched
+            }
+            Err(errors) => {
+                error::print_summary("Failed to load the configuration", &errors, Path::new(""));
+                Watched::new(&config_path, None)
+            }
+        };
+
+        println!("Watching for changes (press Ctrl+C to stop)");
+        let snapshot = watched.snapshot();
+        let changed = loop {
+            thread::sleep(POLL_INTERVAL);
+            let current = watched.snapshot();
+            if current != snapshot {
+                // Give editors that write files in several steps time to finish
+                thread::sleep(POLL_INTERVAL);
+                break changed_files(&snapshot, &watched.snapshot());
+            }
+        };
+
+        for path in &changed {
+            let relative = path.strip_prefix(&watched.config_dir).unwrap_or(path);
+            println!("Changed: {}", relative.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(files: &[(&str, u64, u64)]) -> Snapshot {
+        files
+            .iter()
+            .map(|(path, modified, size)| {
+                let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(*modified);
+                (PathBuf::from(path), (modified, *size))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn changed_files_are_the_added_modified_and_removed_files() {
+        let previous = snapshot(&[
+            ("content/post/kept.md", 1, 10),
+            ("content/post/edited.md", 1, 10),
+            ("content/post/resized.md", 1, 10),
+            ("content/post/deleted.md", 1, 10),
+        ]);
+        let current = snapshot(&[
+            ("content/post/kept.md", 1, 10),
+            ("content/post/edited.md", 2, 10),
+            ("content/post/resized.md", 1, 12),
+            ("content/post/added.md", 2, 10),
+        ]);
+        assert_eq!(
+            changed_files(&previous, &current),
+            [
+                PathBuf::from("content/post/added.md"),
+                PathBuf::from("content/post/edited.md"),
+                PathBuf::from("content/post/resized.md"),
+                PathBuf::from("content/post/deleted.md"),
+            ]
+        );
+        assert!(changed_files(&current, &current).is_empty());
+    }
+
+    #[test]
+    fn snapshot_leaves_out_the_build_folder() {
+        let root = std::env::temp_dir().join(format!("ssg-watch-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for file in [
+            "config.yml",
+            "config.production.yml",
+            "notes.txt",
+            "content/post/post.md",
+            "static/build/site.css",
+            "build/post.html",
+            "src/app.js",
+        ] {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, file).unwrap();
+        }
+        let watched = Watched {
+            config_dir: root.clone(),
+            // The build folder may be within a watched folder
+            dirs: vec![root.join("content"), root.join("static"), root.clone()],
+            files: vec![root.join("src/app.js")],
+            output_dir: Some(root.join("build")),
+        };
+
+        let files: Vec<PathBuf> = watched
+            .snapshot()
+            .into_keys()
+            .map(|path| path.strip_prefix(&root).unwrap().to_path_buf())
+            .collect();
+        for file in [
+            "config.yml",
+            "config.production.yml",
+            "content/post/post.md",
+            "static/build/site.css",
+            "src/app.js",
+        ] {
+            assert!(files.contains(&PathBuf::from(file)), "{}", file);
+        }
+        assert!(!files.contains(&PathBuf::from("build/post.html")));
+
+        // Without a configuration, only the configuration files are watched
+        let watched = Watched {
+            config_dir: root.clone(),
+            dirs: Vec::new(),
+            files: Vec::new(),
+            output_dir: None,
+        };
+        assert_eq!(
+            watched.snapshot().into_keys().collect::<Vec<_>>(),
+            [root.join("config.production.yml"), root.join("config.yml")]
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+}